@@ -0,0 +1,266 @@
+use proc_macro::TokenStream;
+use proc_macro2::{
+    Span,
+    TokenStream as TokenStream2,
+};
+use quote::{
+    quote,
+    ToTokens,
+};
+use syn::{
+    Attribute,
+    Error,
+    Expr,
+    ExprLit,
+    FnArg,
+    Ident,
+    ImplItem,
+    Item,
+    Lit,
+    LitStr,
+    Meta,
+    Pat,
+    Result,
+    Signature,
+    Type,
+    parse_macro_input,
+    spanned::Spanned,
+};
+
+
+/// Turns every function in an `impl` block or inline module into a Continual native.
+///
+/// Generates a `register` function that adds all of them to a `RootScope` and a `NATIVES` constant
+/// describing their names, arity, doc strings and parameter types. Functions are registered with
+/// their Rust name unless renamed with `#[native(name = "...")]`, and can be left out with
+/// `#[native(skip)]`. Natives are pure unless given a capability with
+/// `#[native(capability = Stdout)]`. Methods taking `self` are always skipped.
+#[proc_macro_attribute]
+pub fn native_module(attr: TokenStream, item: TokenStream)->TokenStream {
+    if !attr.is_empty() {
+        return Error::new(TokenStream2::from(attr).span(), "`native_module` takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let item = parse_macro_input!(item as Item);
+    return match expand(item) {
+        Ok(tokens)=>tokens.into(),
+        Err(e)=>e.to_compile_error().into(),
+    };
+}
+
+
+struct NativeFn {
+    name: String,
+    capability: Ident,
+    path: TokenStream2,
+    doc: String,
+    params: Vec<(String, String)>,
+    variadic: bool,
+}
+
+
+fn expand(item: Item)->Result<TokenStream2> {
+    match item {
+        Item::Impl(mut imp)=>{
+            if let Some((_, path, _)) = &imp.trait_ {
+                return Err(Error::new(path.span(), "`native_module` can't be used on trait impls"));
+            }
+
+            let mut natives = Vec::new();
+            for item in imp.items.iter_mut() {
+                let ImplItem::Fn(f) = item else {continue};
+                let Some(opts) = take_native_attrs(&mut f.attrs)? else {continue};
+                if f.sig.receiver().is_some() {
+                    continue;
+                }
+
+                let ident = &f.sig.ident;
+                natives.push(native_fn(&f.sig, &f.attrs, opts, quote!(Self::#ident))?);
+            }
+
+            let generated = generate(&natives);
+            let (impl_generics, _, where_clause) = imp.generics.split_for_impl();
+            let self_ty = &imp.self_ty;
+
+            return Ok(quote!{
+                #imp
+
+                impl #impl_generics #self_ty #where_clause {
+                    #generated
+                }
+            });
+        },
+        Item::Mod(mut module)=>{
+            let Some((_, items)) = &mut module.content else {
+                return Err(Error::new(module.span(), "`native_module` needs an inline module"));
+            };
+
+            let mut natives = Vec::new();
+            for item in items.iter_mut() {
+                let Item::Fn(f) = item else {continue};
+                let Some(opts) = take_native_attrs(&mut f.attrs)? else {continue};
+
+                let ident = &f.sig.ident;
+                natives.push(native_fn(&f.sig, &f.attrs, opts, quote!(#ident))?);
+            }
+
+            let generated = generate(&natives);
+            items.push(Item::Verbatim(generated));
+
+            return Ok(module.into_token_stream());
+        },
+        item=>Err(Error::new(item.span(), "`native_module` can only be used on `impl` blocks and inline modules")),
+    }
+}
+
+/// The options given with `#[native(...)]`.
+#[derive(Default)]
+struct NativeOpts {
+    name: Option<LitStr>,
+    capability: Option<Ident>,
+}
+
+/// Removes the `#[native(...)]` attributes from a function. Returns `None` if it is skipped.
+fn take_native_attrs(attrs: &mut Vec<Attribute>)->Result<Option<NativeOpts>> {
+    let mut skip = false;
+    let mut opts = NativeOpts::default();
+
+    let mut error = None;
+    attrs.retain(|attr|{
+        if !attr.path().is_ident("native") {
+            return true;
+        }
+
+        let res = attr.parse_nested_meta(|meta|{
+            if meta.path.is_ident("skip") {
+                skip = true;
+                return Ok(());
+            }
+            if meta.path.is_ident("name") {
+                opts.name = Some(meta.value()?.parse::<LitStr>()?);
+                return Ok(());
+            }
+            if meta.path.is_ident("capability") {
+                opts.capability = Some(meta.value()?.parse::<Ident>()?);
+                return Ok(());
+            }
+
+            return Err(meta.error("Expected `skip`, `name = \"...\"` or `capability = ...`"));
+        });
+        if let Err(e) = res {
+            error.get_or_insert(e);
+        }
+
+        return false;
+    });
+
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    if skip {
+        return Ok(None);
+    }
+
+    return Ok(Some(opts));
+}
+
+fn native_fn(sig: &Signature, attrs: &[Attribute], opts: NativeOpts, path: TokenStream2)->Result<NativeFn> {
+    let mut params = Vec::new();
+    let mut variadic = false;
+    for (i, arg) in sig.inputs.iter().enumerate() {
+        let FnArg::Typed(arg) = arg else {unreachable!()};
+        if variadic {
+            return Err(Error::new(arg.span(), "`Rest` must be the last parameter of a native"));
+        }
+        variadic = is_rest(&arg.ty);
+
+        let name = match &*arg.pat {
+            Pat::Ident(p)=>p.ident.to_string(),
+            _=>format!("arg{}", i + 1),
+        };
+        params.push((name, type_string(&arg.ty)));
+    }
+
+    return Ok(NativeFn {
+        name: opts.name.map(|n|n.value()).unwrap_or_else(||sig.ident.to_string()),
+        capability: opts.capability.unwrap_or_else(||Ident::new("Pure", Span::call_site())),
+        path,
+        doc: doc_string(attrs),
+        params,
+        variadic,
+    });
+}
+
+fn generate(natives: &[NativeFn])->TokenStream2 {
+    let infos = natives.iter().map(|n|{
+        let NativeFn{name,doc,variadic,capability,..} = n;
+        let arity = if *variadic {
+            quote!(::core::option::Option::None)
+        } else {
+            let len = n.params.len();
+            quote!(::core::option::Option::Some(#len))
+        };
+        let params = n.params.iter().map(|(name, ty)|quote!{
+            ::continual::NativeParam {name: #name, ty: #ty}
+        });
+
+        quote!{
+            ::continual::NativeInfo {
+                name: #name,
+                doc: #doc,
+                arity: #arity,
+                params: &[#(#params),*],
+                capability: ::continual::Capability::#capability,
+            }
+        }
+    });
+    let registers = natives.iter().map(|n|{
+        let NativeFn{name,path,capability,..} = n;
+        quote!(root.register_with_capability(#name, ::continual::Capability::#capability, #path);)
+    });
+
+    return quote!{
+        /// Descriptions of every native in this module, in declaration order.
+        pub const NATIVES: &'static [::continual::NativeInfo] = &[#(#infos),*];
+
+        /// Adds every native in this module to `root`.
+        pub fn register<'a>(root: &mut ::continual::RootScope<'a>) {
+            #(#registers)*
+        }
+    };
+}
+
+fn is_rest(ty: &Type)->bool {
+    match ty {
+        Type::Path(p)=>p.path.segments.last().is_some_and(|s|s.ident == "Rest"),
+        _=>false,
+    }
+}
+
+fn type_string(ty: &Type)->String {
+    let mut out = ty.to_token_stream().to_string();
+    for (from, to) in [(" < ", "<"), ("< ", "<"), (" >", ">"), (" , ", ", "), ("& ", "&"), (" :: ", "::")] {
+        out = out.replace(from, to);
+    }
+
+    return out;
+}
+
+fn doc_string(attrs: &[Attribute])->String {
+    let mut lines = Vec::new();
+    for attr in attrs {
+        let Meta::NameValue(nv) = &attr.meta else {continue};
+        if !nv.path.is_ident("doc") {
+            continue;
+        }
+        if let Expr::Lit(ExprLit{lit: Lit::Str(s),..}) = &nv.value {
+            let line = s.value();
+            lines.push(line.strip_prefix(' ').map(str::to_string).unwrap_or(line));
+        }
+    }
+
+    return lines.join("\n");
+}
@@ -0,0 +1,36 @@
+use crate::{
+    Continuation,
+    Data,
+    Prompts,
+};
+
+
+/// A point `fail` can go back to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Choice<'a> {
+    /// An `amb` with values left to try. `rest` is in reverse, so the next value is last.
+    Alternatives {
+        cont: Continuation<'a>,
+        prompts: Prompts<'a>,
+        rest: Vec<Data<'a>>,
+    },
+    /// An `all-solutions` collecting the solutions found so far. Failing back to it returns them.
+    Collect {
+        cont: Continuation<'a>,
+        prompts: Prompts<'a>,
+        results: Vec<Data<'a>>,
+    },
+}
+
+/// A request to the machine's choice points.
+pub enum AmbOp<'a> {
+    /// Returns the first value to the continuation, and tries the rest on `fail`.
+    Choose(Continuation<'a>, Vec<Data<'a>>),
+    /// Goes back to the most recent choice point.
+    Fail,
+    /// Records a solution for the innermost `all-solutions`, then fails to look for the next one.
+    Solution(Data<'a>),
+    /// Calls the continuation with a return continuation that records each solution, and returns a
+    /// list of them once the choices are used up.
+    Collect(Continuation<'a>, Continuation<'a>),
+}
@@ -0,0 +1,109 @@
+use std::{
+    cell::{
+        Cell,
+        OnceCell,
+        RefCell,
+    },
+    collections::HashSet,
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+    },
+};
+use crate::ast::{
+    Expr,
+    Params,
+};
+
+
+/// Values that can be added through a shared reference, and are kept until the arena is dropped.
+/// They are stored in chunks that double in size, so adding one never moves the others.
+pub(crate) struct Arena<T> {
+    len: Cell<usize>,
+    first: OnceCell<Box<Chunk<T>>>,
+}
+impl<T> Default for Arena<T> {
+    fn default()->Self {
+        Arena {len: Cell::new(0), first: OnceCell::new()}
+    }
+}
+impl<T> Arena<T> {
+    const FIRST_CHUNK: usize = 8;
+
+    pub(crate) fn alloc(&self, value: T)->&T {
+        let mut index = self.len.get();
+        self.len.set(index + 1);
+
+        let mut chunk = self.first.get_or_init(||Chunk::new(Self::FIRST_CHUNK));
+        loop {
+            if let Some(slot) = chunk.slots.get(index) {
+                // `len` only grows, so the slot hasn't been used yet
+                return slot.get_or_init(||value);
+            }
+            index -= chunk.slots.len();
+            let size = chunk.slots.len() * 2;
+            chunk = chunk.next.get_or_init(||Chunk::new(size));
+        }
+    }
+}
+impl<T> Debug for Arena<T> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        f.debug_struct("Arena")
+            .field("len", &self.len.get())
+            .finish_non_exhaustive()
+    }
+}
+
+struct Chunk<T> {
+    slots: Box<[OnceCell<T>]>,
+    next: OnceCell<Box<Self>>,
+}
+impl<T> Chunk<T> {
+    fn new(size: usize)->Box<Self> {
+        Box::new(Chunk {
+            slots: (0..size).map(|_|OnceCell::new()).collect(),
+            next: OnceCell::new(),
+        })
+    }
+}
+
+
+/// The code made for a program besides its source, like the names macros make and the code `eval`
+/// makes from data. It is kept with the program, since continuations borrow it like they borrow the
+/// parsed source.
+#[derive(Debug, Default)]
+pub struct CodeArena<'a> {
+    strings: Arena<Box<str>>,
+    /// Every string in `strings`, so each is only kept once.
+    names: RefCell<HashSet<&'a str>>,
+    functions: Arena<(Params<'a>, [Expr<'a>; 1])>,
+    /// The size of the data the functions were made from, which counts towards `Limits::data_size`.
+    size: Cell<usize>,
+}
+impl<'a> CodeArena<'a> {
+    /// Returns the arena's copy of `s`, adding it if there isn't one yet.
+    pub(crate) fn alloc_str(&'a self, s: &str)->&'a str {
+        if let Some(name) = self.names.borrow().get(s) {
+            return name;
+        }
+
+        let name: &'a str = self.strings.alloc(s.into());
+        self.names.borrow_mut().insert(name);
+
+        return name;
+    }
+
+    /// Keeps a function with a single expression as its body, made from data of the size.
+    pub(crate) fn alloc_function(&'a self, params: Params<'a>, expr: Expr<'a>, size: usize)->(&'a Params<'a>, &'a [Expr<'a>]) {
+        self.size.set(self.size.get() + size);
+        let (params, body) = self.functions.alloc((params, [expr]));
+
+        return (params, body);
+    }
+
+    /// The size of the data every function was made from.
+    pub(crate) fn size(&self)->usize {
+        self.size.get()
+    }
+}
@@ -1,8 +1,44 @@
+use std::fmt::{
+    Display,
+    Formatter,
+    Result as FmtResult,
+};
+use crate::syntax::Syntax;
+
+
 #[derive(Debug, PartialEq)]
 pub enum Expr<'a> {
     DefCont {
         name: &'a str,
-        params: Vec<&'a str>,
+        params: Params<'a>,
+        body: Vec<Self>,
+    },
+    /// Like `DefCont`, but calling it returns a generator that runs the body lazily.
+    DefGen {
+        name: &'a str,
+        params: Params<'a>,
+        body: Vec<Self>,
+    },
+
+    /// Defines a dynamically scoped parameter. `default` is a constant, used outside any
+    /// `parameterize` of it.
+    DefParam {
+        name: &'a str,
+        default: Box<Self>,
+    },
+    /// Runs the body with the parameter bound to `value`. Continuations captured inside the body see
+    /// the binding again whenever they are re-entered.
+    Parameterize {
+        name: &'a str,
+        value: Box<Self>,
+        body: Vec<Self>,
+    },
+
+    /// Runs the body with a continuation mark from `key` to `value` on the current frame. A mark
+    /// set in tail position of another with the same key replaces it.
+    WithContMark {
+        key: Box<Self>,
+        value: Box<Self>,
         body: Vec<Self>,
     },
 
@@ -15,15 +51,68 @@ pub enum Expr<'a> {
     Call {
         to_call: Box<Self>,
         args: Vec<Self>,
+        /// The index in `args` and the name of each argument passed as `:name value`.
+        keywords: Vec<(usize, &'a str)>,
     },
 
     Apply {
         lhs: Box<Self>,
         args: Vec<Self>,
+        keywords: Vec<(usize, &'a str)>,
     },
 
     Begin(Vec<Self>),
 
+    /// Runs the body, and if it throws, runs the handler with the error in `$var`.
+    Try {
+        body: Vec<Self>,
+        var: &'a str,
+        handler: Vec<Self>,
+    },
+
+    /// Runs `before`, `body` and then `after`. `after` also runs whenever a continuation jumps out
+    /// of the body, and `before` whenever one jumps back in.
+    DynamicWind {
+        before: Box<Self>,
+        body: Box<Self>,
+        after: Box<Self>,
+    },
+
+    /// Runs the body with handlers for the effects it performs. Also delimits continuations like
+    /// `reset`.
+    Handle {
+        body: Box<Self>,
+        handlers: Vec<Handler<'a>>,
+    },
+    /// Runs the nearest handler for `effect` with the arguments and the continuation of the
+    /// `perform`, up to the `handle`.
+    Perform {
+        effect: &'a str,
+        args: Vec<Self>,
+    },
+
+    /// Delimits the continuations captured by `shift` inside it.
+    Reset(Vec<Self>),
+    /// Captures the continuation up to the nearest `reset` in `$var` and runs the body in its place.
+    Shift {
+        var: &'a str,
+        body: Vec<Self>,
+    },
+
+    /// Waits until one of the clauses can send or receive, does it and runs that clause's body.
+    /// Runs `default` instead of waiting if there is one.
+    Select {
+        clauses: Vec<SelectClause<'a>>,
+        default: Option<Vec<Self>>,
+    },
+
+    /// Runs the body of the first clause whose pattern matches the value and whose guard is true,
+    /// with the variables the pattern binds.
+    Match {
+        value: Box<Self>,
+        clauses: Vec<MatchClause<'a>>,
+    },
+
     IfElse {
         cond: Box<Self>,
         expr: Box<Self>,
@@ -32,6 +121,12 @@ pub enum Expr<'a> {
 
     /// Setting a var also defines it if it isn't already defined.
     SetVar(&'a str, Box<Self>),
+    /// Sets each var to one of the values the expression returns, like `SetVar`. The number of
+    /// values has to match.
+    Receive {
+        vars: Vec<&'a str>,
+        expr: Box<Self>,
+    },
     GetVar(&'a str),
 
     SetField {
@@ -46,6 +141,123 @@ pub enum Expr<'a> {
 
     Number(i64),
     String(&'a str),
+    /// `:name` at a call site passes the argument after it to the parameter called `name`.
+    Keyword(&'a str),
+    /// `'syntax` or `(quote syntax)`: the code as data, without evaluating it.
+    Quote(Syntax<'a>),
     Bool(bool),
     None,
 }
+
+/// The parameters of a `defCont` or `defGen`: `[ret a (b default) &rest xs]`.
+#[derive(Debug, PartialEq, Default)]
+pub struct Params<'a> {
+    pub required: Vec<&'a str>,
+    /// Parameters that can be left out. The default is evaluated when the call doesn't give one,
+    /// with the parameters before it already bound.
+    pub optional: Vec<(&'a str, Expr<'a>)>,
+    /// Collects the arguments left over into a list.
+    pub rest: Option<&'a str>,
+}
+impl<'a> Params<'a> {
+    /// The name of every parameter, in order.
+    pub fn names(&self)->impl Iterator<Item = &'a str> + '_ {
+        self.required.iter()
+            .copied()
+            .chain(self.optional.iter().map(|(name, _)|*name))
+            .chain(self.rest)
+    }
+}
+/// Shows the shape of the parameters, like `[ret a (b) &rest xs]`.
+impl<'a> Display for Params<'a> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        let required = self.required.iter().map(|name|name.to_string());
+        let optional = self.optional.iter().map(|(name, _)|format!("({name})"));
+        let rest = self.rest.map(|name|format!("&rest {name}"));
+        let parts: Vec<_> = required.chain(optional).chain(rest).collect();
+
+        write!(f, "[{}]", parts.join(" "))
+    }
+}
+
+/// A clause of `handle`. The continuation of the `perform` is passed as the last parameter.
+#[derive(Debug, PartialEq)]
+pub struct Handler<'a> {
+    pub effect: &'a str,
+    pub params: Vec<&'a str>,
+    pub body: Vec<Expr<'a>>,
+}
+
+/// A clause of `select`.
+#[derive(Debug, PartialEq)]
+pub enum SelectClause<'a> {
+    /// `(recv channel [var] body...)`
+    Recv {
+        channel: Expr<'a>,
+        var: &'a str,
+        body: Vec<Expr<'a>>,
+    },
+    /// `(send channel value body...)`
+    Send {
+        channel: Expr<'a>,
+        value: Expr<'a>,
+        body: Vec<Expr<'a>>,
+    },
+}
+impl<'a> SelectClause<'a> {
+    /// The expressions evaluated before waiting: the channel, and the value for `send`.
+    pub fn operands(&self)->impl Iterator<Item = &Expr<'a>> {
+        let (channel, value) = match self {
+            Self::Recv{channel,..}=>(channel, None),
+            Self::Send{channel,value,..}=>(channel, Some(value)),
+        };
+
+        return std::iter::once(channel).chain(value);
+    }
+
+    pub fn body(&self)->&[Expr<'a>] {
+        match self {
+            Self::Recv{body,..}|Self::Send{body,..}=>body,
+        }
+    }
+}
+
+/// A clause of `match`: `(pattern body...)`, or `(pattern :when guard body...)`.
+#[derive(Debug, PartialEq)]
+pub struct MatchClause<'a> {
+    pub pattern: Pattern<'a>,
+    /// Evaluated with the pattern's variables bound. The clause only runs if it is `#t`.
+    pub guard: Option<Expr<'a>>,
+    pub body: Vec<Expr<'a>>,
+}
+
+/// The shape of a value in a `match` clause.
+#[derive(Debug, PartialEq)]
+pub enum Pattern<'a> {
+    /// `_` matches anything.
+    Wildcard,
+    /// A name matches anything, and binds the value to it.
+    Bind(&'a str),
+    /// A number, string, keyword, quoted code, bool or `#n` matches a value equal to it.
+    Literal(Expr<'a>),
+    /// `[a b &rest xs]` matches a list of the items, with any left over matching `rest`.
+    List {
+        items: Vec<Pattern<'a>>,
+        rest: Option<Box<Pattern<'a>>>,
+    },
+    /// Matches a value equal to any of the literals. `case` uses it for the datums of a clause.
+    AnyOf(Vec<Expr<'a>>),
+}
+impl<'a> Pattern<'a> {
+    /// The name of every variable the pattern binds.
+    pub fn vars(&self)->Vec<&'a str> {
+        match self {
+            Self::Wildcard|Self::Literal(_)|Self::AnyOf(_)=>Vec::new(),
+            Self::Bind(name)=>vec![name],
+            Self::List{items,rest}=>items.iter()
+                .chain(rest.as_deref())
+                .flat_map(Self::vars)
+                .collect(),
+        }
+    }
+}
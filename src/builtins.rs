@@ -0,0 +1,448 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::rc::Rc;
+use crate::{
+    syntax::SQUARE,
+    AmbOp,
+    Capability,
+    Continuation,
+    ContRet,
+    Data,
+    Exception,
+    Channel,
+    Engine,
+    ErrorKind,
+    GenOp,
+    Generator,
+    Goal,
+    LVar,
+    Rest,
+    RootScope,
+    ScriptError,
+    Search,
+    ThreadId,
+    ThreadOp,
+};
+
+
+/// Registers every builtin native.
+pub fn register<'a>(root: &mut RootScope<'a>) {
+    Builtins::register(root);
+
+    root.register_cont("yield-host", yield_host);
+    root.register_cont("throw", throw);
+    root.register_cont("spawn", spawn);
+    root.register_cont("yield", yield_thread);
+    root.register_cont("join", join);
+    root.register_cont("current-thread", current_thread);
+    root.register_cont("send", send);
+    root.register_cont("recv", recv);
+    root.register_cont("close", close);
+    root.register_cont("engine", engine);
+    root.register_cont("emit", emit);
+    root.register_cont("next", next);
+    root.register_cont("gen-done?", gen_done);
+    root.register_cont("for-each", for_each);
+    root.register_cont("amb", amb);
+    root.register_cont("fail", fail);
+    root.register_cont("all-solutions", all_solutions);
+    root.register_cont("values", values);
+    root.register_cont("current-cont-marks", current_cont_marks);
+    root.register_cont("run", run);
+    root.register_cont_with_capability("eval", Capability::Eval, eval);
+}
+
+/// `(yield-host ret value)`: suspends the machine and hands `value` to the host. `ret` gets the
+/// host's reply when it resumes the program.
+pub fn yield_host<'a>(ret: Continuation<'a>, value: Data<'a>)->ContRet<'a> {
+    return ContRet::Suspend(ret, value);
+}
+
+
+/// `(throw ret value)`: throws `value` to the nearest `catch`. Errors that were caught are rethrown
+/// as they are.
+pub fn throw<'a>(_: Continuation<'a>, value: Data<'a>)->ContRet<'a> {
+    return ContRet::Throw(value);
+}
+
+
+/// `(spawn ret f args...)`: starts a green thread that calls `f` with `args`, and returns its handle.
+/// The new thread runs once the current one yields, blocks or finishes.
+pub fn spawn<'a>(ret: Continuation<'a>, cont: Continuation<'a>, args: Rest<Data<'a>>)->ContRet<'a> {
+    return ContRet::Thread(ThreadOp::Spawn {ret, cont, args: args.0});
+}
+
+
+/// `(yield ret)`: lets every other ready thread run before continuing.
+pub fn yield_thread<'a>(ret: Continuation<'a>)->ContRet<'a> {
+    return ContRet::Thread(ThreadOp::Yield(ret));
+}
+
+
+/// `(join ret thread)`: waits for `thread` to finish and returns its result.
+pub fn join<'a>(ret: Continuation<'a>, id: ThreadId)->ContRet<'a> {
+    return ContRet::Thread(ThreadOp::Join(ret, id));
+}
+
+
+/// `(current-thread ret)`: returns the handle of the running thread.
+pub fn current_thread<'a>(ret: Continuation<'a>)->ContRet<'a> {
+    return ContRet::Thread(ThreadOp::Current(ret));
+}
+
+
+/// `(send ret channel value)`: sends `value`, waiting until there is room in the channel or a
+/// receiver takes it. Sending to a closed channel is an error.
+pub fn send<'a>(ret: Continuation<'a>, ch: Channel<'a>, value: Data<'a>)->ContRet<'a> {
+    return ContRet::Thread(ThreadOp::Send(ret, ch, value));
+}
+
+
+/// `(recv ret channel)`: receives the next value, waiting until one is sent. Returns `#n` once the
+/// channel is closed and empty.
+pub fn recv<'a>(ret: Continuation<'a>, ch: Channel<'a>)->ContRet<'a> {
+    return ContRet::Thread(ThreadOp::Recv(ret, ch));
+}
+
+
+/// `(close ret channel)`: stops any more values from being sent. Waiting receivers get `#n`.
+pub fn close<'a>(ret: Continuation<'a>, ch: Channel<'a>)->ContRet<'a> {
+    return ContRet::Thread(ThreadOp::Close(ret, ch));
+}
+
+
+/// `(engine ret f fuel)`: calls `f` for up to `fuel` reductions. Returns its result if it finished,
+/// or an engine holding the rest of the computation if it ran out of fuel. Passing that engine
+/// instead of `f` continues it.
+pub fn engine<'a>(ret: Continuation<'a>, f: Data<'a>, fuel: i64)->Result<ContRet<'a>> {
+    let Ok(fuel) = u64::try_from(fuel) else {
+        bail!(ScriptError::new(ErrorKind::Type, "An engine's fuel can't be negative"));
+    };
+    let engine = match f {
+        Data::Continuation(cont)=>Engine::new(cont),
+        Data::Engine(engine)=>Rc::unwrap_or_clone(engine),
+        d=>bail!(ScriptError::new(ErrorKind::Type, format!("Expected a continuation or engine to run, but got {}", d.type_name()))),
+    };
+
+    return Ok(ContRet::Engine(ret, engine, fuel));
+}
+
+
+/// `(emit ret value)`: hands `value` to whoever asked the innermost generator for its next value,
+/// and stops the generator until the value after it is asked for.
+pub fn emit<'a>(ret: Continuation<'a>, value: Data<'a>)->ContRet<'a> {
+    return ContRet::Emit(ret, value);
+}
+
+
+/// `(next ret generator)`: runs the generator until it emits a value and returns it, or returns `#n`
+/// once the generator is done.
+pub fn next<'a>(ret: Continuation<'a>, generator: Generator<'a>)->ContRet<'a> {
+    return ContRet::Generator(ret, generator, GenOp::Next);
+}
+
+
+/// `(gen-done? ret generator)`: returns `#t` if the generator has no values left. Runs the
+/// generator up to its next `emit` to find out, and keeps the value for `next`.
+pub fn gen_done<'a>(ret: Continuation<'a>, generator: Generator<'a>)->ContRet<'a> {
+    return ContRet::Generator(ret, generator, GenOp::Done);
+}
+
+
+/// `(for-each ret generator f)`: calls `f` with every value the generator has left.
+pub fn for_each<'a>(ret: Continuation<'a>, generator: Generator<'a>, f: Continuation<'a>)->ContRet<'a> {
+    return ContRet::Generator(ret, generator, GenOp::ForEach(f));
+}
+
+
+/// `(amb ret values...)`: returns the first value, and goes back to return the next one each time
+/// `fail` is called. With no values, it fails right away.
+pub fn amb<'a>(ret: Continuation<'a>, values: Rest<Data<'a>>)->ContRet<'a> {
+    return ContRet::Amb(AmbOp::Choose(ret, values.0));
+}
+
+
+/// `(fail ret)`: goes back to the most recent `amb` that has values left. It is an error if there
+/// are none.
+pub fn fail<'a>(_: Continuation<'a>)->ContRet<'a> {
+    return ContRet::Amb(AmbOp::Fail);
+}
+
+
+/// `(all-solutions ret f)`: calls `f`, and fails every time it returns to find every other way it
+/// can return. Returns a list of what it returned, in order.
+pub fn all_solutions<'a>(ret: Continuation<'a>, f: Continuation<'a>)->ContRet<'a> {
+    return ContRet::Amb(AmbOp::Collect(ret, f));
+}
+
+
+/// `(values ret values...)`: returns every value to `ret`, like `(apply ret values...)`.
+pub fn values<'a>(ret: Continuation<'a>, values: Rest<Data<'a>>)->ContRet<'a> {
+    return ContRet::Apply(ret, values.0);
+}
+
+
+/// `(current-cont-marks ret key)`: returns a list of the values of every continuation mark set with
+/// `key` around the call, innermost first.
+pub fn current_cont_marks<'a>(ret: Continuation<'a>, key: Data<'a>)->ContRet<'a> {
+    return ContRet::Marks(ret, key);
+}
+
+
+/// `(run ret n f)`: calls `f` with a new logic variable, and returns a list of up to `n` values of
+/// the variable that satisfy the goal `f` returns. With an `n` of `#n`, returns every value.
+pub fn run<'a>(ret: Continuation<'a>, limit: Option<i64>, cont: Continuation<'a>)->Result<ContRet<'a>> {
+    let Ok(limit) = limit.map(usize::try_from).transpose() else {
+        bail!(ScriptError::new(ErrorKind::Type, "`run` can't find a negative number of answers"));
+    };
+
+    let query = LVar::new();
+    let goal = Goal::Fresh {
+        count: 0,
+        cont,
+        args: vec![Data::LVar(query.clone())],
+    };
+    return Search::new(ret, query, Rc::new(goal), limit).run();
+}
+
+
+/// `(eval ret code)`: runs quoted code, like `'(add 1 2)`, and returns its value to `ret`. The code
+/// runs on its own, so it only sees global continuations and not the caller's variables, and it can
+/// only use the natives the caller can.
+pub fn eval<'a>(ret: Continuation<'a>, code: Data<'a>)->ContRet<'a> {
+    return ContRet::Eval(ret, code);
+}
+
+
+/// The typed natives every program gets.
+pub struct Builtins;
+#[continual::native_module]
+impl Builtins {
+    /// Returns `#t` if every argument is equal to the first.
+    fn eq<'a>(args: Rest<Data<'a>>)->bool {
+        let mut args_iter = args.0.into_iter();
+        let Some(first) = args_iter.next() else {return true};
+
+        return args_iter.all(|arg|arg == first);
+    }
+
+    /// Returns `#t` if every argument is `#t`. Only used when `and` is passed as a value, since
+    /// `(and ...)` itself is a special form that stops at the first argument that isn't `#t`.
+    fn and<'a>(args: Rest<Data<'a>>)->bool {
+        return args.0.into_iter().all(|arg|arg == Data::Bool(true));
+    }
+
+    /// Returns `#t` if any argument is `#t`. Only used when `or` is passed as a value, since
+    /// `(or ...)` itself is a special form that stops at the first argument that is `#t`.
+    fn or<'a>(args: Rest<Data<'a>>)->bool {
+        return args.0.into_iter().any(|arg|arg == Data::Bool(true));
+    }
+
+    /// Sums the arguments.
+    fn add(nums: Rest<i64>)->i64 {
+        return nums.0.into_iter().sum();
+    }
+
+    /// Subtracts the rest of the arguments from the first.
+    fn sub(nums: Rest<i64>)->i64 {
+        let mut nums = nums.0.into_iter();
+        let first = nums.next().unwrap_or(0);
+
+        return nums.fold(first, |total, n|total - n);
+    }
+
+    /// Multiplies the arguments.
+    fn mul(nums: Rest<i64>)->i64 {
+        let mut nums = nums.0.into_iter();
+        let first = nums.next().unwrap_or(0);
+
+        return nums.fold(first, |total, n|total * n);
+    }
+
+    /// Takes the remainder of the first argument by each of the rest in turn.
+    fn rem(nums: Rest<i64>)->Result<i64> {
+        let mut nums = nums.0.into_iter();
+        let mut rem = nums.next().unwrap_or(0);
+        for n in nums {
+            let Some(res) = rem.checked_rem(n) else {
+                bail!("Division by zero");
+            };
+            rem = res;
+        }
+
+        return Ok(rem);
+    }
+
+    /// The message of a caught error.
+    #[native(name = "error-message")]
+    fn error_message<'a>(e: Rc<Exception<'a>>)->String {
+        return e.message.clone();
+    }
+
+    /// The kind of a caught error: `user`, `type`, `unbound-variable`, `arity` or `runtime`.
+    #[native(name = "error-kind")]
+    fn error_kind<'a>(e: Rc<Exception<'a>>)->String {
+        return e.kind.to_string();
+    }
+
+    /// The value passed to `throw`, or the message for errors raised by the interpreter.
+    #[native(name = "error-value")]
+    fn error_value<'a>(e: Rc<Exception<'a>>)->Data<'a> {
+        return e.value.clone();
+    }
+
+    /// The line the error was raised on, or `#n` if it is unknown.
+    #[native(name = "error-line")]
+    fn error_line<'a>(e: Rc<Exception<'a>>)->Option<i64> {
+        return e.location.map(|l|l.line as i64);
+    }
+
+    /// The column the error was raised at, or `#n` if it is unknown.
+    #[native(name = "error-column")]
+    fn error_column<'a>(e: Rc<Exception<'a>>)->Option<i64> {
+        return e.location.map(|l|l.column as i64);
+    }
+
+    /// Makes a channel that buffers up to `capacity` values. With a capacity of 0, every `send` waits
+    /// for a `recv`.
+    fn chan<'a>(capacity: i64)->Result<Data<'a>> {
+        let Ok(capacity) = usize::try_from(capacity) else {
+            bail!("A channel's capacity can't be negative");
+        };
+
+        return Ok(Data::Channel(Channel::new(capacity)));
+    }
+
+    /// Returns `#t` if the argument is an engine that ran out of fuel.
+    #[native(name = "engine?")]
+    fn is_engine<'a>(data: Data<'a>)->bool {
+        return matches!(data, Data::Engine(_));
+    }
+
+    /// Makes a list of the arguments.
+    fn list<'a>(items: Rest<Data<'a>>)->Vec<Data<'a>> {
+        return items.0;
+    }
+
+    /// The number of items in a list.
+    fn length<'a>(list: Rc<Vec<Data<'a>>>)->i64 {
+        return list.len() as i64;
+    }
+
+    /// The item at `index`, counting from 0.
+    fn nth<'a>(list: Rc<Vec<Data<'a>>>, index: i64)->Result<Data<'a>> {
+        let Some(item) = usize::try_from(index).ok().and_then(|i|list.get(i)) else {
+            bail!("Index {index} is out of bounds for a list of length {}", list.len());
+        };
+
+        return Ok(item.clone());
+    }
+
+    /// Joins lists together into a new list.
+    fn append<'a>(lists: Rest<Rc<Vec<Data<'a>>>>)->Vec<Data<'a>> {
+        return lists.0.iter()
+            .flat_map(|l|l.iter().cloned())
+            .collect();
+    }
+
+    /// The symbol with the name.
+    fn symbol<'a>(name: String)->Data<'a> {
+        return Data::Symbol(name.into());
+    }
+
+    /// Returns `#t` if the argument is a symbol.
+    #[native(name = "symbol?")]
+    fn is_symbol<'a>(data: Data<'a>)->bool {
+        return matches!(data, Data::Symbol(_));
+    }
+
+    /// The name of a symbol.
+    #[native(name = "symbol-name")]
+    fn symbol_name<'a>(symbol: Data<'a>)->Result<String> {
+        let Data::Symbol(name) = symbol else {
+            bail!("Expected symbol, but got {}", symbol.type_name());
+        };
+
+        return Ok(name.to_string());
+    }
+
+    /// A goal that succeeds if `a` and `b` unify.
+    #[native(name = "==")]
+    fn unify<'a>(a: Data<'a>, b: Data<'a>)->Data<'a> {
+        return Data::Goal(Rc::new(Goal::Unify(a, b)));
+    }
+
+    /// A goal that succeeds if every goal does. A list of goals can be used in place of a goal.
+    fn all<'a>(goals: Rest<Rc<Goal<'a>>>)->Data<'a> {
+        return Data::Goal(Rc::new(Goal::All(goals.0)));
+    }
+
+    /// A goal that succeeds once for every way any of the goals does. A list of goals can be used in
+    /// place of a goal.
+    fn conde<'a>(goals: Rest<Rc<Goal<'a>>>)->Data<'a> {
+        return Data::Goal(Rc::new(Goal::Any(goals.0)));
+    }
+
+    /// A goal that calls `f` with `args` followed by `count` new logic variables, and succeeds like
+    /// the goal `f` returns. `f` is only called when `run` gets to the goal, so relations can refer
+    /// to themselves through `fresh`.
+    fn fresh<'a>(count: i64, f: Continuation<'a>, args: Rest<Data<'a>>)->Result<Data<'a>> {
+        let Ok(count) = usize::try_from(count) else {
+            bail!(ScriptError::new(ErrorKind::Type, "`fresh` can't make a negative number of variables"));
+        };
+
+        return Ok(Data::Goal(Rc::new(Goal::Fresh {count, cont: f, args: args.0})));
+    }
+
+    /// Prints every argument followed by a newline.
+    #[native(name = "println", capability = Stdout)]
+    fn println_native<'a>(msgs: Rest<Data<'a>>)->Result<()> {
+        if msgs.0.is_empty() {
+            bail!("Expected 2 args for println");
+        }
+
+        for msg in &msgs.0 {
+            print_data(msg);
+        }
+        println!();
+
+        return Ok(());
+    }
+}
+
+
+fn print_data(data: &Data) {
+    match data {
+        Data::String(s)=>print!("{s}"),
+        Data::Number(n)=>print!("{n}"),
+        Data::Bool(true)=>print!("#t"),
+        Data::Bool(false)=>print!("#f"),
+        Data::None=>print!("#n"),
+        Data::Continuation(_)=>print!("<cont>"),
+        Data::Error(e)=>print!("<error: {}>", e.message),
+        Data::Thread(_)=>print!("<thread>"),
+        Data::Channel(_)=>print!("<channel>"),
+        Data::Engine(_)=>print!("<engine>"),
+        Data::Generator(_)=>print!("<generator>"),
+        Data::Keyword(k)=>print!(":{k}"),
+        Data::Symbol(name)=>print!("{name}"),
+        Data::LVar(_)=>print!("<logic-var>"),
+        Data::Goal(_)=>print!("<goal>"),
+        Data::List(items)=>{
+            let (open, close, items) = match items.split_first() {
+                Some((Data::Symbol(head), rest)) if &**head == SQUARE=>("[", "]", rest),
+                _=>("(", ")", &items[..]),
+            };
+            print!("{open}");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    print!(" ");
+                }
+                print_data(item);
+            }
+            print!("{close}");
+        },
+    }
+}
@@ -0,0 +1,270 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    collections::HashSet,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+};
+use crate::{
+    ast::*,
+    RootScope,
+};
+
+
+/// What a native is allowed to do to the world outside the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// No side effects outside the program. Always granted.
+    Pure,
+    Stdout,
+    FsRead,
+    FsWrite,
+    Process,
+    Clock,
+    Random,
+    /// Running code made at runtime with `eval`.
+    Eval,
+}
+impl Capability {
+    pub const ALL: [Capability; 8] = [
+        Self::Pure,
+        Self::Stdout,
+        Self::FsRead,
+        Self::FsWrite,
+        Self::Process,
+        Self::Clock,
+        Self::Random,
+        Self::Eval,
+    ];
+
+    fn bit(self)->u8 {
+        1 << (self as u8)
+    }
+}
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        let name = match self {
+            Self::Pure=>"pure",
+            Self::Stdout=>"stdout",
+            Self::FsRead=>"filesystem-read",
+            Self::FsWrite=>"filesystem-write",
+            Self::Process=>"process",
+            Self::Clock=>"clock",
+            Self::Random=>"random",
+            Self::Eval=>"eval",
+        };
+
+        return f.write_str(name);
+    }
+}
+
+/// A set of capabilities. `Pure` is always included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u8);
+impl Default for Capabilities {
+    fn default()->Self {
+        Self::all()
+    }
+}
+impl Capabilities {
+    pub fn all()->Self {
+        Capabilities(u8::MAX)
+    }
+
+    pub fn pure()->Self {
+        Capabilities(Capability::Pure.bit())
+    }
+
+    pub fn with(self, cap: Capability)->Self {
+        Capabilities(self.0 | cap.bit())
+    }
+
+    pub fn without(self, cap: Capability)->Self {
+        if cap == Capability::Pure {
+            return self;
+        }
+
+        return Capabilities(self.0 & !cap.bit());
+    }
+
+    pub fn contains(&self, cap: Capability)->bool {
+        self.0 & cap.bit() != 0
+    }
+
+    /// The capabilities in both sets.
+    pub fn intersect(self, other: Self)->Self {
+        Capabilities(self.0 & other.0)
+    }
+}
+impl FromIterator<Capability> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I)->Self {
+        iter.into_iter().fold(Self::pure(), Self::with)
+    }
+}
+
+
+/// Checks that every native referenced by `exprs` is allowed by `caps`. Names bound inside the
+/// program (parameters, `set`, `receive`, `letcc`, `defCont`, `defGen` and `defParam`) shadow natives and are not checked.
+pub fn check_links<'a>(root: &RootScope<'a>, exprs: &[Expr<'a>], caps: Capabilities)->Result<()> {
+    let mut bound = HashSet::new();
+    for expr in exprs {
+        if let Expr::DefCont{name,..}|Expr::DefGen{name,..}|Expr::DefParam{name,..} = expr {
+            bound.insert(*name);
+        }
+    }
+
+    for expr in exprs {
+        check_expr(root, expr, caps, &mut bound)?;
+    }
+
+    return Ok(());
+}
+
+fn check_expr<'a>(root: &RootScope<'a>, expr: &Expr<'a>, caps: Capabilities, bound: &mut HashSet<&'a str>)->Result<()> {
+    match expr {
+        Expr::DefCont{name,params,body}|Expr::DefGen{name,params,body}=>{
+            bound.insert(name);
+            let mut inner = bound.clone();
+            inner.extend(params.names());
+            for (_, default) in &params.optional {
+                check_expr(root, default, caps, &mut inner)?;
+            }
+            check_block(root, body, caps, inner)?;
+        },
+        Expr::DefParam{name,..}=>{
+            bound.insert(name);
+        },
+        Expr::Parameterize{value,body,..}=>{
+            check_expr(root, value, caps, bound)?;
+            check_block(root, body, caps, bound.clone())?;
+        },
+        Expr::WithContMark{key,value,body}=>{
+            check_expr(root, key, caps, bound)?;
+            check_expr(root, value, caps, bound)?;
+            check_block(root, body, caps, bound.clone())?;
+        },
+        Expr::LetCC{var,body}=>{
+            let mut inner = bound.clone();
+            inner.insert(var);
+            check_expr(root, body, caps, &mut inner)?;
+        },
+        Expr::Call{to_call:lhs,args,..}|Expr::Apply{lhs,args,..}=>{
+            check_expr(root, lhs, caps, bound)?;
+            for arg in args {
+                check_expr(root, arg, caps, bound)?;
+            }
+        },
+        Expr::Begin(exprs)=>for expr in exprs {
+            check_expr(root, expr, caps, bound)?;
+        },
+        Expr::Try{body,var,handler}=>{
+            check_block(root, body, caps, bound.clone())?;
+            let mut inner = bound.clone();
+            inner.insert(var);
+            check_block(root, handler, caps, inner)?;
+        },
+        Expr::Handle{body,handlers}=>{
+            check_expr(root, body, caps, bound)?;
+            for handler in handlers {
+                let mut inner = bound.clone();
+                inner.extend(handler.params.iter().copied());
+                check_block(root, &handler.body, caps, inner)?;
+            }
+        },
+        Expr::Perform{args,..}=>for arg in args {
+            check_expr(root, arg, caps, bound)?;
+        },
+        Expr::Reset(body)=>check_block(root, body, caps, bound.clone())?,
+        Expr::Shift{var,body}=>{
+            let mut inner = bound.clone();
+            inner.insert(var);
+            check_block(root, body, caps, inner)?;
+        },
+        Expr::Select{clauses,default}=>{
+            for clause in clauses {
+                for operand in clause.operands() {
+                    check_expr(root, operand, caps, bound)?;
+                }
+                let mut inner = bound.clone();
+                if let SelectClause::Recv{var,..} = clause {
+                    inner.insert(var);
+                }
+                check_block(root, clause.body(), caps, inner)?;
+            }
+            if let Some(default) = default {
+                check_block(root, default, caps, bound.clone())?;
+            }
+        },
+        Expr::Match{value,clauses}=>{
+            check_expr(root, value, caps, bound)?;
+            for clause in clauses {
+                let mut inner = bound.clone();
+                inner.extend(clause.pattern.vars());
+                if let Some(guard) = &clause.guard {
+                    check_expr(root, guard, caps, &mut inner)?;
+                }
+                check_block(root, &clause.body, caps, inner)?;
+            }
+        },
+        Expr::IfElse{cond,expr,default}=>{
+            check_expr(root, cond, caps, bound)?;
+            check_expr(root, expr, caps, bound)?;
+            if let Some(default) = default {
+                check_expr(root, default, caps, bound)?;
+            }
+        },
+        Expr::DynamicWind{before,body,after}=>{
+            check_expr(root, before, caps, bound)?;
+            check_expr(root, body, caps, bound)?;
+            check_expr(root, after, caps, bound)?;
+        },
+        Expr::SetVar(_,data)|Expr::Receive{expr:data,..}=>check_expr(root, data, caps, bound)?,
+        Expr::SetField{lhs,data,..}=>{
+            check_expr(root, lhs, caps, bound)?;
+            check_expr(root, data, caps, bound)?;
+        },
+        Expr::GetField{data,..}=>check_expr(root, data, caps, bound)?,
+        Expr::GetVar(name)=>if !bound.contains(name) {
+            if let Some(cap) = root.native_capability(name) {
+                if !caps.contains(cap) {
+                    bail!("`{name}` needs the `{cap}` capability, which this program is not granted");
+                }
+            }
+        },
+        Expr::Number(_)|Expr::String(_)|Expr::Keyword(_)|Expr::Quote(_)|Expr::Bool(_)|Expr::None=>{},
+    }
+
+    return Ok(());
+}
+
+/// Checks a body whose `set`s are local to it.
+fn check_block<'a>(root: &RootScope<'a>, body: &[Expr<'a>], caps: Capabilities, mut bound: HashSet<&'a str>)->Result<()> {
+    for expr in body {
+        bind(expr, &mut bound);
+    }
+    for expr in body {
+        check_expr(root, expr, caps, &mut bound)?;
+    }
+
+    return Ok(());
+}
+
+/// Adds the variables a block always defines with `set`, `receive`, `defCont` and `defGen`. They are
+/// visible in the whole block, since a re-entered continuation can see them before the definition
+/// runs again. Names that are only defined in a branch are still checked, and natives are checked
+/// again when they are looked up, so shadowing can't be used to reach a denied native.
+fn bind<'a>(expr: &Expr<'a>, bound: &mut HashSet<&'a str>) {
+    match expr {
+        Expr::SetVar(name,_)|Expr::DefCont{name,..}|Expr::DefGen{name,..}=>{
+            bound.insert(name);
+        },
+        Expr::Receive{vars,..}=>bound.extend(vars.iter().copied()),
+        Expr::Begin(exprs)=>exprs.iter().for_each(|expr|bind(expr, bound)),
+        _=>{},
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    cell::{
+        Cell,
+        RefCell,
+    },
+    collections::VecDeque,
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+    },
+    rc::Rc,
+};
+use crate::{
+    Data,
+    ErrorKind,
+    Parked,
+    ScriptError,
+};
+
+
+/// A thread waiting to send or receive. The waiters of a `select` share `fired`, so only the first
+/// channel that becomes ready wakes the thread, and the rest are skipped.
+#[derive(Debug)]
+pub(crate) struct Waiter<'a> {
+    pub parked: Parked<'a>,
+    /// The value being sent. Unused for receivers.
+    pub value: Data<'a>,
+    pub fired: Option<Rc<Cell<bool>>>,
+}
+impl<'a> Waiter<'a> {
+    /// Marks the waiter as woken. Returns `false` if a `select` was already woken by another channel.
+    fn fire(&self)->bool {
+        match &self.fired {
+            Some(fired)=>!fired.replace(true),
+            None=>true,
+        }
+    }
+}
+
+/// What happened when sending to a channel.
+pub(crate) enum Sent<'a> {
+    /// The value was taken, waking the receiver if there was one waiting.
+    Done(Option<Parked<'a>>),
+    /// The buffer is full and no receiver is waiting, so the sender has to wait.
+    Full(Data<'a>),
+}
+
+#[derive(Debug)]
+pub(crate) struct ChannelState<'a> {
+    capacity: usize,
+    pub buffer: VecDeque<Data<'a>>,
+    pub senders: VecDeque<Waiter<'a>>,
+    pub receivers: VecDeque<Waiter<'a>>,
+    closed: bool,
+}
+
+/// A queue for passing values between green threads. Sending to a full channel and receiving from
+/// an empty one park the thread until another thread receives or sends. Channels are compared by
+/// identity.
+#[derive(Clone)]
+pub struct Channel<'a>(Rc<RefCell<ChannelState<'a>>>);
+impl<'a> Channel<'a> {
+    /// A channel that holds up to `capacity` values before senders wait. With a capacity of 0 every
+    /// send waits for a receiver.
+    pub fn new(capacity: usize)->Self {
+        Channel(Rc::new(RefCell::new(ChannelState {
+            capacity,
+            buffer: VecDeque::new(),
+            senders: VecDeque::new(),
+            receivers: VecDeque::new(),
+            closed: false,
+        })))
+    }
+
+    pub fn is_closed(&self)->bool {
+        self.0.borrow().closed
+    }
+
+    /// The number of buffered values.
+    pub fn len(&self)->usize {
+        self.0.borrow().buffer.len()
+    }
+
+    pub fn is_empty(&self)->bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn state(&self)->&RefCell<ChannelState<'a>> {
+        &self.0
+    }
+
+    /// Hands `value` to a waiting receiver, or buffers it if there is room.
+    pub(crate) fn try_send(&self, value: Data<'a>)->Result<Sent<'a>> {
+        let mut state = self.0.borrow_mut();
+        if state.closed {
+            bail!(ScriptError::new(ErrorKind::Runtime, "Sent to a closed channel"));
+        }
+
+        while let Some(waiter) = state.receivers.pop_front() {
+            if waiter.fire() {
+                let mut parked = waiter.parked;
+                parked.args = vec![value];
+                return Ok(Sent::Done(Some(parked)));
+            }
+        }
+        if state.buffer.len() < state.capacity {
+            state.buffer.push_back(value);
+            return Ok(Sent::Done(None));
+        }
+
+        return Ok(Sent::Full(value));
+    }
+
+    /// Takes the next value, waking the first waiting sender. Receiving from a closed channel gives
+    /// `#n` once it is empty. Returns `None` if the receiver has to wait.
+    pub(crate) fn try_recv(&self)->Option<(Data<'a>, Option<Parked<'a>>)> {
+        let mut state = self.0.borrow_mut();
+        let buffered = state.buffer.pop_front();
+
+        while let Some(waiter) = state.senders.pop_front() {
+            if waiter.fire() {
+                let mut parked = waiter.parked;
+                parked.args = vec![Data::None];
+                let value = match buffered {
+                    Some(value)=>{
+                        state.buffer.push_back(waiter.value);
+                        value
+                    },
+                    None=>waiter.value,
+                };
+                return Some((value, Some(parked)));
+            }
+        }
+
+        match buffered {
+            Some(value)=>return Some((value, None)),
+            None if state.closed=>return Some((Data::None, None)),
+            None=>return None,
+        }
+    }
+
+    pub(crate) fn wait_to_send(&self, waiter: Waiter<'a>) {
+        self.0.borrow_mut().senders.push_back(waiter);
+    }
+
+    pub(crate) fn wait_to_recv(&self, waiter: Waiter<'a>) {
+        self.0.borrow_mut().receivers.push_back(waiter);
+    }
+
+    /// Stops any more values from being sent, and wakes every waiting receiver with `#n`. Values
+    /// already sent can still be received.
+    pub(crate) fn close(&self)->Result<Vec<Parked<'a>>> {
+        let mut state = self.0.borrow_mut();
+        if state.closed {
+            bail!(ScriptError::new(ErrorKind::Runtime, "The channel is already closed"));
+        }
+        state.closed = true;
+
+        let woken = state.receivers.drain(..)
+            .filter(Waiter::fire)
+            .map(|waiter|Parked {args: vec![Data::None], ..waiter.parked})
+            .collect();
+
+        return Ok(woken);
+    }
+
+    pub(crate) fn as_ptr(&self)->*const () {
+        Rc::as_ptr(&self.0).cast()
+    }
+}
+impl<'a> PartialEq for Channel<'a> {
+    fn eq(&self, other: &Self)->bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl<'a> Debug for Channel<'a> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        let state = self.0.borrow();
+        write!(f, "Channel({}/{})", state.buffer.len(), state.capacity)
+    }
+}
@@ -0,0 +1,71 @@
+use crate::ast::*;
+
+
+/// `(if cond expr default)`
+fn if_else<'a>(cond: Expr<'a>, expr: Expr<'a>, default: Expr<'a>)->Expr<'a> {
+    Expr::IfElse {
+        cond: Box::new(cond),
+        expr: Box::new(expr),
+        default: Some(Box::new(default)),
+    }
+}
+
+/// `(and a b...)` is `(if a (and b...) #f)`, and the last one is `(if a #t #f)`.
+pub(crate) fn and(args: Vec<Expr>)->Expr {
+    return args.into_iter()
+        .rev()
+        .fold(Expr::Bool(true), |rest, arg|if_else(arg, rest, Expr::Bool(false)));
+}
+
+/// `(or a b...)` is `(if a #t (or b...))`, and the last one is `(if a #t #f)`.
+pub(crate) fn or(args: Vec<Expr>)->Expr {
+    return args.into_iter()
+        .rev()
+        .fold(Expr::Bool(false), |rest, arg|if_else(arg, Expr::Bool(true), rest));
+}
+
+/// `(when cond body...)` is `(if cond (begin body...))`.
+pub(crate) fn when<'a>(cond: Expr<'a>, body: Vec<Expr<'a>>)->Expr<'a> {
+    Expr::IfElse {
+        cond: Box::new(cond),
+        expr: Box::new(Expr::Begin(body)),
+        default: None,
+    }
+}
+
+/// `(unless cond body...)` is `(if cond #n (begin body...))`.
+pub(crate) fn unless<'a>(cond: Expr<'a>, body: Vec<Expr<'a>>)->Expr<'a> {
+    return if_else(cond, Expr::None, Expr::Begin(body));
+}
+
+/// `(cond (test body...)... (else body...))` is a chain of `if`s, returning `#n` if no test is true
+/// and there is no `else`.
+pub(crate) fn cond<'a>(clauses: Vec<(Expr<'a>, Vec<Expr<'a>>)>, default: Option<Vec<Expr<'a>>>)->Expr<'a> {
+    let default = default.map(Expr::Begin).unwrap_or(Expr::None);
+
+    return clauses.into_iter()
+        .rev()
+        .fold(default, |rest, (test, body)|if_else(test, Expr::Begin(body), rest));
+}
+
+/// `(case value ((datums...) body...)... (else body...))` is a `match` with a pattern for each clause
+/// that matches any of its datums, returning `#n` if none are equal and there is no `else`.
+pub(crate) fn case<'a>(value: Expr<'a>, clauses: Vec<(Vec<Expr<'a>>, Vec<Expr<'a>>)>, default: Option<Vec<Expr<'a>>>)->Expr<'a> {
+    let mut clauses: Vec<_> = clauses.into_iter()
+        .map(|(datums, body)|MatchClause {
+            pattern: Pattern::AnyOf(datums),
+            guard: None,
+            body,
+        })
+        .collect();
+    clauses.push(MatchClause {
+        pattern: Pattern::Wildcard,
+        guard: None,
+        body: default.unwrap_or_default(),
+    });
+
+    return Expr::Match {
+        value: Box::new(value),
+        clauses,
+    };
+}
@@ -0,0 +1,37 @@
+use std::rc::Rc;
+use crate::{
+    Continuation,
+    Data,
+    Frames,
+};
+
+
+/// A computation that ran out of fuel, returned by `engine`. Running it again with more fuel
+/// continues where it stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Engine<'a> {
+    pub(crate) cont: Continuation<'a>,
+    pub(crate) args: Vec<Data<'a>>,
+}
+impl<'a> Engine<'a> {
+    /// An engine that calls `cont` with a return continuation that finishes the engine.
+    pub fn new(cont: Continuation<'a>)->Self {
+        let ret = Continuation::Normal {
+            env: Rc::default(),
+            frames: Frames::default(),
+        };
+
+        return Engine {
+            cont,
+            args: vec![Data::Continuation(ret)],
+        };
+    }
+
+    /// The computation left when an engine ran out of fuel while returning `value` to `cont`.
+    pub(crate) fn stopped(cont: Continuation<'a>, value: Data<'a>)->Self {
+        Engine {
+            cont,
+            args: vec![value],
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    collections::HashMap,
+    rc::Rc,
+};
+use crate::{
+    arena::CodeArena,
+    ast::*,
+    native::*,
+    machine::*,
+    capability::*,
+    exception::*,
+    threads::*,
+    channel::*,
+    engine::*,
+    generator::*,
+    amb::*,
+    logic::*,
+    symbol::*,
+};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Data<'a> {
+    Continuation(Continuation<'a>),
+    Error(Rc<Exception<'a>>),
+    Thread(ThreadId),
+    Channel(Channel<'a>),
+    Engine(Rc<Engine<'a>>),
+    Generator(Generator<'a>),
+    List(Rc<Vec<Data<'a>>>),
+    Keyword(&'a str),
+    /// A name, as in quoted code. Symbols with the same name are equal.
+    Symbol(Symbol),
+    LVar(LVar),
+    Goal(Rc<Goal<'a>>),
+    String(String),
+    Number(i64),
+    Bool(bool),
+    None,
+}
+impl<'a> Data<'a> {
+    pub fn type_name(&self)->&'static str {
+        match self {
+            Self::Continuation(_)=>"continuation",
+            Self::Error(_)=>"error",
+            Self::Thread(_)=>"thread",
+            Self::Channel(_)=>"channel",
+            Self::Engine(_)=>"engine",
+            Self::Generator(_)=>"generator",
+            Self::List(_)=>"list",
+            Self::Keyword(_)=>"keyword",
+            Self::Symbol(_)=>"symbol",
+            Self::LVar(_)=>"logic-var",
+            Self::Goal(_)=>"goal",
+            Self::String(_)=>"string",
+            Self::Number(_)=>"number",
+            Self::Bool(_)=>"bool",
+            Self::None=>"none",
+        }
+    }
+}
+
+/// What a native asks the machine to do next.
+pub enum ContRet<'a> {
+    Apply(Continuation<'a>, Vec<Data<'a>>),
+    /// Suspends the machine and hands the data to the host. The continuation is resumed with the
+    /// host's reply.
+    Suspend(Continuation<'a>, Data<'a>),
+    /// Throws the data to the nearest `catch`.
+    Throw(Data<'a>),
+    /// Asks the scheduler to spawn, switch or wait for green threads.
+    Thread(ThreadOp<'a>),
+    /// Runs the engine for up to `fuel` reductions, and applies the continuation to its result or to
+    /// the engine that is left.
+    Engine(Continuation<'a>, Engine<'a>, u64),
+    /// Runs the generator until it emits a value or finishes, and applies the continuation to what
+    /// the op asked for.
+    Generator(Continuation<'a>, Generator<'a>, GenOp<'a>),
+    /// Hands the value to the innermost running generator, which continues with the continuation
+    /// when the next value is asked for.
+    Emit(Continuation<'a>, Data<'a>),
+    /// Applies the continuation to a list of the values of every continuation mark with the key,
+    /// innermost first.
+    Marks(Continuation<'a>, Data<'a>),
+    /// Makes a choice point, or goes back to one.
+    Amb(AmbOp<'a>),
+    /// Runs code made from the data, with the capabilities of the code that asked, and applies the
+    /// continuation to its value.
+    Eval(Continuation<'a>, Data<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Continuation<'a> {
+    Native(Native<'a>),
+    /// Stops the machine and returns the first argument. Used as the return continuation when
+    /// calling into Continual from Rust.
+    Return,
+    /// The rest of a computation: the frames waiting for a value and the scopes they run in.
+    Normal {
+        env: Rc<Env<'a>>,
+        frames: Frames<'a>,
+    },
+    /// The part of a computation captured by `shift` up to the nearest `reset`, or by `perform` up to
+    /// its `handle`. Calling it runs those frames on top of the caller's and returns their result,
+    /// so it can be called any number of times. `prompts` are the `reset`s and `handle`s inside the
+    /// captured part, and `handlers` are reinstalled around it.
+    Delimited {
+        env: Rc<Env<'a>>,
+        frames: Frames<'a>,
+        prompts: Prompts<'a>,
+        handlers: &'a [Handler<'a>],
+    },
+    /// A `defCont`. `caps` are the capabilities of the program it is defined in, which its body
+    /// runs with.
+    Function {
+        params: &'a Params<'a>,
+        body: &'a [Expr<'a>],
+        caps: Capabilities,
+    },
+    /// A `defGen`. Calling it returns a generator that runs the body when values are asked for.
+    GeneratorFunction {
+        params: &'a Params<'a>,
+        body: &'a [Expr<'a>],
+        caps: Capabilities,
+    },
+}
+impl<'a> Continuation<'a> {
+    pub fn run(self, root: &RootScope<'a>, args: Vec<Data<'a>>)->Result<Data<'a>> {
+        Machine::new(root, self, args).run()
+    }
+}
+
+
+#[derive(Default)]
+pub struct RootScope<'a> {
+    conts: HashMap<&'a str, Continuation<'a>>,
+    /// The default of every parameter.
+    params: HashMap<&'a str, &'a Expr<'a>>,
+    /// The capability each native needs.
+    natives: HashMap<&'a str, Capability>,
+    capabilities: Capabilities,
+    /// The source of every loaded program, for error locations.
+    sources: Vec<&'a str>,
+    /// Where code made while running is kept. Belongs to the first loaded program.
+    code: Option<&'a CodeArena<'a>>,
+}
+impl<'a> RootScope<'a> {
+    pub fn new(exprs: &'a [Expr<'a>])->Self {
+        let mut root = RootScope::default();
+        root.load(exprs);
+
+        return root;
+    }
+
+    /// Adds every top-level `defCont`, `defGen` and `defParam` in `exprs`, replacing any existing definitions with the same name.
+    pub fn load(&mut self, exprs: &'a [Expr<'a>]) {
+        self.define(exprs, Capabilities::all());
+    }
+
+    fn define(&mut self, exprs: &'a [Expr<'a>], caps: Capabilities) {
+        for expr in exprs {
+            match expr {
+                Expr::DefCont{name,params,body}=>{
+                    let cont = Continuation::Function {
+                        body: body.as_slice(),
+                        params,
+                        caps,
+                    };
+                    self.natives.remove(name);
+                    self.conts.insert(*name, cont);
+                },
+                Expr::DefGen{name,params,body}=>{
+                    let cont = Continuation::GeneratorFunction {
+                        body: body.as_slice(),
+                        params,
+                        caps,
+                    };
+                    self.natives.remove(name);
+                    self.conts.insert(*name, cont);
+                },
+                Expr::DefParam{name,default}=>{
+                    self.params.insert(*name, default);
+                },
+                _=>{},
+            }
+        }
+    }
+
+    /// Like `load`, but first checks that `exprs` only references natives allowed by `caps` and by
+    /// this scope's capabilities. The definitions are also limited to those natives when they run.
+    pub fn link(&mut self, exprs: &'a [Expr<'a>], caps: Capabilities)->Result<()> {
+        let caps = caps.intersect(self.capabilities);
+        check_links(self, exprs, caps)?;
+        self.define(exprs, caps);
+
+        return Ok(());
+    }
+
+    /// Remembers the source a program was parsed from, so errors in it have a location.
+    pub fn add_source(&mut self, source: &'a str) {
+        self.sources.push(source);
+    }
+
+    /// Keeps code made while running, like by `eval`, in `code` unless a program already does.
+    pub(crate) fn add_code(&mut self, code: &'a CodeArena<'a>) {
+        self.code.get_or_insert(code);
+    }
+
+    pub(crate) fn code(&self)->Option<&'a CodeArena<'a>> {
+        self.code
+    }
+
+    /// Finds where `s` is in the loaded sources.
+    pub fn locate(&self, s: &str)->Option<Location> {
+        self.sources.iter().find_map(|source|Location::find(source, s))
+    }
+
+    pub fn run_cont(&self, name: &str, args: Vec<Data<'a>>)->Result<Data<'a>> {
+        if let Some(cont) = self.get(name) {
+            return cont.run(self, args);
+        }
+
+        bail!("No continuation named `{name}`");
+    }
+
+    /// The default of a parameter, or `None` if there is no parameter called `name`.
+    pub fn param(&self, name: &str)->Option<&'a Expr<'a>> {
+        self.params.get(name).copied()
+    }
+
+    /// Gets a global continuation. Natives needing a capability this scope doesn't have are hidden.
+    pub fn get(&self, name: &str)->Option<Continuation<'a>> {
+        if let Some(cap) = self.natives.get(name) {
+            if !self.capabilities.contains(*cap) {
+                return None;
+            }
+        }
+
+        return self.conts.get(name).cloned();
+    }
+
+    /// The capability needed by the native called `name`, or `None` if there is no such native.
+    pub fn native_capability(&self, name: &str)->Option<Capability> {
+        self.natives.get(name).copied()
+    }
+
+    /// Restricts the natives programs in this scope can use.
+    pub fn set_capabilities(&mut self, caps: Capabilities) {
+        self.capabilities = caps;
+    }
+
+    pub fn capabilities(&self)->Capabilities {
+        self.capabilities
+    }
+
+    /// Adds a native that doesn't need any capabilities.
+    pub fn add_native<F: Fn(&RootScope<'a>, Vec<Data<'a>>)->Result<ContRet<'a>> + 'a>(&mut self, name: &'a str, f: F) {
+        self.add_native_with_capability(name, Capability::Pure, f);
+    }
+
+    pub fn add_native_with_capability<F: Fn(&RootScope<'a>, Vec<Data<'a>>)->Result<ContRet<'a>> + 'a>(&mut self, name: &'a str, cap: Capability, f: F) {
+        self.natives.insert(name, cap);
+        self.conts.insert(name, Continuation::Native(Native::new(f)));
+    }
+
+    /// Registers a typed native that doesn't need any capabilities. The implicit continuation is
+    /// handled automatically, so `f` only sees the converted arguments, e.g.
+    /// `root.register("add", |a: i64, b: i64|a + b)`.
+    pub fn register<Args, F: IntoNative<'a, Args>>(&mut self, name: &'a str, f: F) {
+        self.register_with_capability(name, Capability::Pure, f);
+    }
+
+    pub fn register_with_capability<Args, F: IntoNative<'a, Args>>(&mut self, name: &'a str, cap: Capability, f: F) {
+        self.natives.insert(name, cap);
+        self.conts.insert(name, Continuation::Native(f.into_native(name)));
+    }
+
+    /// Registers a native that takes its return continuation and tells the machine what to do next,
+    /// with the other arguments checked like a typed native's. See `builtins::yield_host`.
+    pub fn register_cont<Args, F: IntoContNative<'a, Args>>(&mut self, name: &'a str, f: F) {
+        self.register_cont_with_capability(name, Capability::Pure, f);
+    }
+
+    pub fn register_cont_with_capability<Args, F: IntoContNative<'a, Args>>(&mut self, name: &'a str, cap: Capability, f: F) {
+        self.natives.insert(name, cap);
+        self.conts.insert(name, Continuation::Native(f.into_cont_native(name)));
+    }
+}
+
+
+pub fn ret_cont_data<'a>(cont: Data<'a>, args: Vec<Data<'a>>)->Result<ContRet<'a>> {
+    match cont {
+        Data::Continuation(cont)=>return Ok(ContRet::Apply(cont, args)),
+        _=>bail!("Expected continuation for first argument"),
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    error::Error,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+};
+use crate::{
+    ast::*,
+    Data,
+};
+
+
+/// What went wrong, so handlers can tell errors apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A value thrown with `throw`.
+    User,
+    /// A value of the wrong type, like calling a number.
+    Type,
+    Unbound,
+    /// The wrong number of arguments.
+    Arity,
+    /// Any other error raised by a native or the interpreter.
+    Runtime,
+}
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        let name = match self {
+            Self::User=>"user",
+            Self::Type=>"type",
+            Self::Unbound=>"unbound-variable",
+            Self::Arity=>"arity",
+            Self::Runtime=>"runtime",
+        };
+
+        return f.write_str(name);
+    }
+}
+
+/// An error with a kind. Natives can return one to choose how the error is seen by `catch`; other
+/// errors are caught as `runtime` errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+impl ScriptError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>)->Self {
+        ScriptError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        f.write_str(&self.message)
+    }
+}
+impl Error for ScriptError {}
+
+/// The error raised when nothing catches a thrown value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uncaught {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub location: Option<Location>,
+}
+impl Display for Uncaught {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "Uncaught {} error: {}", self.kind, self.message)?;
+        if let Some(location) = self.location {
+            write!(f, " at {location}")?;
+        }
+
+        return Ok(());
+    }
+}
+impl Error for Uncaught {}
+
+
+/// A line and column in a program's source, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+impl Location {
+    /// Finds where `s` starts, if it is a slice of `source`.
+    pub fn find(source: &str, s: &str)->Option<Self> {
+        let start = source.as_ptr() as usize;
+        let offset = (s.as_ptr() as usize).checked_sub(start)?;
+        if offset > source.len() {
+            return None;
+        }
+
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i|i + 1);
+        let column = before[line_start..].chars().count() + 1;
+
+        return Some(Location {line, column});
+    }
+}
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+
+/// A caught error, as seen by the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exception<'a> {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The thrown value, or the message for errors raised by the interpreter.
+    pub value: Data<'a>,
+    pub location: Option<Location>,
+}
+impl<'a> Exception<'a> {
+    /// Wraps a value passed to `throw`.
+    pub fn thrown(value: Data<'a>, location: Option<Location>)->Self {
+        let message = match &value {
+            Data::String(s)=>s.clone(),
+            Data::Number(n)=>n.to_string(),
+            Data::Bool(b)=>b.to_string(),
+            d=>format!("a {} was thrown", d.type_name()),
+        };
+
+        return Exception {
+            kind: ErrorKind::User,
+            message,
+            value,
+            location,
+        };
+    }
+
+    pub fn from_error(error: &anyhow::Error, location: Option<Location>)->Self {
+        let (kind, message) = match error.downcast_ref::<ScriptError>() {
+            Some(e)=>(e.kind, e.message.clone()),
+            None=>(ErrorKind::Runtime, error.to_string()),
+        };
+
+        return Exception {
+            kind,
+            value: Data::String(message.clone()),
+            message,
+            location,
+        };
+    }
+
+    pub fn uncaught(&self)->Uncaught {
+        Uncaught {
+            kind: self.kind,
+            message: self.message.clone(),
+            location: self.location,
+        }
+    }
+}
+
+
+/// The first piece of source text in an expression, used to find where it is.
+pub(crate) fn anchor<'a>(expr: &Expr<'a>)->Option<&'a str> {
+    match expr {
+        Expr::DefCont{name,..}|Expr::DefGen{name,..}|Expr::DefParam{name,..}|Expr::SetVar(name,_)|Expr::GetVar(name)=>Some(name),
+        Expr::Parameterize{name,..}=>Some(name),
+        Expr::LetCC{var,..}|Expr::Shift{var,..}=>Some(var),
+        Expr::Perform{effect,..}=>Some(effect),
+        Expr::Handle{body,..}=>anchor(body),
+        Expr::Call{to_call:lhs,..}|Expr::Apply{lhs,..}|Expr::SetField{lhs,..}=>anchor(lhs),
+        Expr::GetField{data,..}=>anchor(data),
+        Expr::IfElse{cond,..}=>anchor(cond),
+        Expr::Receive{expr,..}|Expr::Match{value:expr,..}=>anchor(expr),
+        Expr::WithContMark{key,..}=>anchor(key),
+        Expr::DynamicWind{before,body,..}=>anchor(before).or_else(||anchor(body)),
+        Expr::Begin(exprs)|Expr::Reset(exprs)=>exprs.first().and_then(anchor),
+        Expr::Try{body,..}=>body.first().and_then(anchor),
+        Expr::Select{clauses,default}=>clauses.iter()
+            .flat_map(SelectClause::operands)
+            .chain(default.iter().flatten())
+            .find_map(anchor),
+        Expr::String(s)|Expr::Keyword(s)=>Some(s),
+        Expr::Number(_)|Expr::Quote(_)|Expr::Bool(_)|Expr::None=>None,
+    }
+}
@@ -0,0 +1,187 @@
+use crate::ast::{
+    Expr,
+    MatchClause,
+    Pattern,
+};
+
+
+/// A row of patterns still to be matched, one for each part of the value. `None` matches anything.
+type Row<'p, 'a> = Vec<Option<&'p Pattern<'a>>>;
+
+
+/// Returns a value, written like a pattern, that none of the clauses match, if there is one.
+/// Clauses with a guard might not run, so they aren't counted. The value is assumed to have the
+/// type the patterns test for: only bools if every pattern is `#t` or `#f`, only lists if every
+/// pattern is a list pattern, and so on. Otherwise only `_` or a variable matches every value.
+pub fn missing<'a>(clauses: &[MatchClause<'a>])->Option<String> {
+    let rows = clauses.iter()
+        .filter(|clause|clause.guard.is_none())
+        .map(|clause|vec![Some(&clause.pattern)])
+        .collect();
+
+    return missing_in(rows, 1).map(|mut witness|witness.remove(0));
+}
+
+
+/// The value tested by a literal pattern, as far as telling the possible values apart goes.
+#[derive(Clone, Copy, PartialEq)]
+enum Literal {
+    Bool(bool),
+    None,
+    Other,
+}
+impl Literal {
+    fn of(expr: &Expr)->Self {
+        match expr {
+            Expr::Bool(b)=>Self::Bool(*b),
+            Expr::None=>Self::None,
+            _=>Self::Other,
+        }
+    }
+}
+
+/// What a pattern needs from the start of a row.
+enum Shape<'p, 'a> {
+    Any,
+    Literals(Vec<Literal>),
+    /// A list of exactly the items, or at least as many if `rest` is true.
+    List {
+        items: Vec<&'p Pattern<'a>>,
+        rest: bool,
+    },
+    /// A list pattern whose rest pattern is a literal, which is only checked when the program runs.
+    Unknown,
+}
+impl<'p, 'a> Shape<'p, 'a> {
+    fn of(pattern: Option<&'p Pattern<'a>>)->Self {
+        match pattern {
+            None|Some(Pattern::Wildcard|Pattern::Bind(_))=>Self::Any,
+            Some(Pattern::Literal(lit))=>Self::Literals(vec![Literal::of(lit)]),
+            Some(Pattern::AnyOf(lits))=>Self::Literals(lits.iter().map(Literal::of).collect()),
+            Some(Pattern::List{items,rest})=>{
+                // `[a &rest [b &rest c]]` is the same as `[a b &rest c]`
+                let mut items: Vec<_> = items.iter().collect();
+                let mut rest = rest.as_deref();
+                while let Some(Pattern::List{items: more, rest: more_rest}) = rest {
+                    items.extend(more);
+                    rest = more_rest.as_deref();
+                }
+                match rest {
+                    None=>Self::List {items, rest: false},
+                    Some(Pattern::Wildcard|Pattern::Bind(_))=>Self::List {items, rest: true},
+                    Some(_)=>Self::Unknown,
+                }
+            },
+        }
+    }
+}
+
+/// The kinds of value a pattern can tell apart, for types where all of them can be listed.
+#[derive(Clone, Copy)]
+enum Ctor {
+    Bool(bool),
+    None,
+    List(usize),
+}
+impl Ctor {
+    /// The number of parts of the value the patterns look at next.
+    fn arity(self)->usize {
+        match self {
+            Self::List(len)=>len,
+            _=>0,
+        }
+    }
+
+    fn show(self, parts: Vec<String>)->String {
+        match self {
+            Self::Bool(true)=>"#t".to_string(),
+            Self::Bool(false)=>"#f".to_string(),
+            Self::None=>"#n".to_string(),
+            Self::List(_)=>format!("[{}]", parts.join(" ")),
+        }
+    }
+}
+
+
+/// Returns the parts of a value of `width` parts that none of the rows match, if there is one.
+fn missing_in<'p, 'a>(rows: Vec<Row<'p, 'a>>, width: usize)->Option<Vec<String>> {
+    if width == 0 {
+        return rows.is_empty().then(Vec::new);
+    }
+
+    let Some(ctors) = ctors(&rows) else {
+        // only rows that match anything at the start can match a value of some other type
+        let rows = rows.into_iter()
+            .filter(|row|matches!(Shape::of(row[0]), Shape::Any))
+            .map(|row|row[1..].to_vec())
+            .collect();
+        let mut witness = missing_in(rows, width - 1)?;
+        witness.insert(0, "_".to_string());
+
+        return Some(witness);
+    };
+
+    for ctor in ctors {
+        let specialized = rows.iter()
+            .filter_map(|row|specialize(row, ctor))
+            .collect();
+        if let Some(mut witness) = missing_in(specialized, ctor.arity() + width - 1) {
+            let parts = witness.drain(..ctor.arity()).collect();
+            witness.insert(0, ctor.show(parts));
+
+            return Some(witness);
+        }
+    }
+
+    return None;
+}
+
+/// Every kind of value the patterns at the start of the rows can tell apart, if they all test for
+/// one type.
+fn ctors(rows: &[Row])->Option<Vec<Ctor>> {
+    let shapes: Vec<_> = rows.iter()
+        .map(|row|Shape::of(row[0]))
+        .filter(|shape|!matches!(shape, Shape::Any))
+        .collect();
+    let literals = || shapes.iter().flat_map(|shape|match shape {
+        Shape::Literals(lits)=>lits.clone(),
+        _=>vec![Literal::Other],
+    });
+
+    if shapes.is_empty() {
+        return None;
+    }
+    if literals().all(|lit|matches!(lit, Literal::Bool(_))) {
+        return Some(vec![Ctor::Bool(true), Ctor::Bool(false)]);
+    }
+    if literals().all(|lit|lit == Literal::None) {
+        return Some(vec![Ctor::None]);
+    }
+
+    let mut longest = 0;
+    for shape in &shapes {
+        let Shape::List{items,..} = shape else {
+            return None;
+        };
+        longest = longest.max(items.len());
+    }
+    // lists longer than every pattern are all matched by the same ones
+    return Some((0..=longest + 1).map(Ctor::List).collect());
+}
+
+/// The rest of the row with the parts of a value of the kind, if the row can match it.
+fn specialize<'p, 'a>(row: &Row<'p, 'a>, ctor: Ctor)->Option<Row<'p, 'a>> {
+    let parts = match (Shape::of(row[0]), ctor) {
+        (Shape::Any, _)=>vec![None; ctor.arity()],
+        (Shape::Literals(lits), Ctor::Bool(b)) if lits.contains(&Literal::Bool(b))=>Vec::new(),
+        (Shape::Literals(lits), Ctor::None) if lits.contains(&Literal::None)=>Vec::new(),
+        (Shape::List{items,rest}, Ctor::List(len)) if items.len() == len || (rest && items.len() < len)=>{
+            let mut parts: Row = items.into_iter().map(Some).collect();
+            parts.resize(len, None);
+            parts
+        },
+        _=>return None,
+    };
+
+    return Some(parts.into_iter().chain(row[1..].iter().copied()).collect());
+}
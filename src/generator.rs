@@ -0,0 +1,86 @@
+use std::{
+    cell::RefCell,
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+    },
+    mem,
+    rc::Rc,
+};
+use crate::{
+    Continuation,
+    Data,
+};
+
+
+/// Where a generator is in its body.
+#[derive(Debug)]
+pub(crate) enum GenState<'a> {
+    /// Applying `cont` to `args` computes the next value.
+    Paused {
+        cont: Continuation<'a>,
+        args: Vec<Data<'a>>,
+    },
+    /// The next value was already computed by `gen-done?`. Applying `cont` to `#n` computes the one
+    /// after it.
+    Emitted {
+        value: Data<'a>,
+        cont: Continuation<'a>,
+    },
+    Running,
+    Done,
+}
+
+/// What the caller of a generator wants from it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenOp<'a> {
+    /// The next value, or `#n` once the generator is done.
+    Next,
+    /// Whether the generator is done, computing the next value if it isn't known yet.
+    Done,
+    /// Calls the continuation with every value that is left.
+    ForEach(Continuation<'a>),
+}
+
+/// A lazy sequence of the values a `defGen` body emits. The body only runs when a value is asked
+/// for, and stops at each `emit`. Generators are compared by identity.
+#[derive(Clone)]
+pub struct Generator<'a>(Rc<RefCell<GenState<'a>>>);
+impl<'a> Generator<'a> {
+    /// A generator that computes its first value by applying `cont` to `args`.
+    pub(crate) fn new(cont: Continuation<'a>, args: Vec<Data<'a>>)->Self {
+        Generator(Rc::new(RefCell::new(GenState::Paused {cont, args})))
+    }
+
+    pub fn is_done(&self)->bool {
+        matches!(*self.0.borrow(), GenState::Done)
+    }
+
+    /// Takes the state, leaving the generator running.
+    pub(crate) fn start(&self)->GenState<'a> {
+        mem::replace(&mut *self.0.borrow_mut(), GenState::Running)
+    }
+
+    pub(crate) fn set(&self, state: GenState<'a>) {
+        *self.0.borrow_mut() = state;
+    }
+
+    pub(crate) fn state(&self)->&RefCell<GenState<'a>> {
+        &self.0
+    }
+
+    pub(crate) fn as_ptr(&self)->*const () {
+        Rc::as_ptr(&self.0).cast()
+    }
+}
+impl<'a> PartialEq for Generator<'a> {
+    fn eq(&self, other: &Self)->bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl<'a> Debug for Generator<'a> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        f.write_str("Generator")
+    }
+}
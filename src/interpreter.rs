@@ -0,0 +1,220 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::cell::OnceCell;
+use crate::{
+    arena::CodeArena,
+    ast::Expr,
+    builtins,
+    parser::Parser,
+    Capabilities,
+    Capability,
+    Continuation,
+    ContRet,
+    Data,
+    IntoContNative,
+    IntoData,
+    IntoNative,
+    Limits,
+    Machine,
+    Resumer,
+    RootScope,
+};
+
+
+/// Parsed source code. Kept separate from the `Interpreter` because continuations borrow the AST.
+#[derive(Debug)]
+pub struct Program<'a> {
+    source: &'a str,
+    /// Parsed again the first time they are used, so the names macros make can be kept in `code`.
+    exprs: OnceCell<Vec<Expr<'a>>>,
+    /// The names made by macros, and the code made while the program runs.
+    code: CodeArena<'a>,
+}
+impl<'a> PartialEq for Program<'a> {
+    fn eq(&self, other: &Self)->bool {
+        self.source == other.source
+    }
+}
+impl<'a> Program<'a> {
+    /// Checks that the source parses. The program can't keep what it parsed yet, since the names
+    /// macros make have to live in the program itself.
+    pub fn parse(source: &'a str)->Result<Self> {
+        Parser::new(source, &CodeArena::default()).parse()?;
+
+        return Ok(Program {source, exprs: OnceCell::new(), code: CodeArena::default()});
+    }
+
+    pub fn exprs(&'a self)->&'a [Expr<'a>] {
+        self.exprs.get_or_init(||{
+            Parser::new(self.source, &self.code).parse()
+                .expect("the source was parsed by `Program::parse`")
+        })
+    }
+
+    pub fn source(&self)->&'a str {
+        self.source
+    }
+
+    pub(crate) fn code(&self)->&CodeArena<'a> {
+        &self.code
+    }
+}
+
+
+/// The embedding API. Holds the root scope of every loaded program and native.
+pub struct Interpreter<'a> {
+    root: RootScope<'a>,
+    limits: Limits,
+}
+impl<'a> Default for Interpreter<'a> {
+    fn default()->Self {
+        Self::new()
+    }
+}
+impl<'a> Interpreter<'a> {
+    /// Creates an interpreter with the builtin natives registered.
+    pub fn new()->Self {
+        let mut root = RootScope::default();
+        builtins::register(&mut root);
+
+        return Interpreter {root, limits: Limits::default()};
+    }
+
+    /// Creates an interpreter without any natives.
+    pub fn empty()->Self {
+        Interpreter {root: RootScope::default(), limits: Limits::default()}
+    }
+
+    /// Adds the `defCont`s of `program`. Later definitions replace earlier ones with the same name.
+    /// Fails if the program references a native the interpreter's capabilities don't allow.
+    pub fn load(&mut self, program: &'a Program<'a>)->Result<()> {
+        self.root.link(program.exprs(), Capabilities::all())?;
+        self.root.add_source(program.source());
+        self.root.add_code(program.code());
+
+        return Ok(());
+    }
+
+    /// Like `load`, but the program may only reference natives allowed by `caps`. Continuations
+    /// passed to it by other programs can still do whatever they were allowed to.
+    pub fn load_with_capabilities(&mut self, program: &'a Program<'a>, caps: Capabilities)->Result<()> {
+        self.root.link(program.exprs(), caps)?;
+        self.root.add_source(program.source());
+        self.root.add_code(program.code());
+
+        return Ok(());
+    }
+
+    /// Restricts the natives every program can use. Natives needing other capabilities are hidden.
+    pub fn set_capabilities(&mut self, caps: Capabilities) {
+        self.root.set_capabilities(caps);
+    }
+
+    pub fn add_native<F: Fn(&RootScope<'a>, Vec<Data<'a>>)->Result<ContRet<'a>> + 'a>(&mut self, name: &'a str, f: F) {
+        self.root.add_native(name, f);
+    }
+
+    pub fn add_native_with_capability<F: Fn(&RootScope<'a>, Vec<Data<'a>>)->Result<ContRet<'a>> + 'a>(&mut self, name: &'a str, cap: Capability, f: F) {
+        self.root.add_native_with_capability(name, cap, f);
+    }
+
+    pub fn register<Args, F: IntoNative<'a, Args>>(&mut self, name: &'a str, f: F) {
+        self.root.register(name, f);
+    }
+
+    pub fn register_with_capability<Args, F: IntoNative<'a, Args>>(&mut self, name: &'a str, cap: Capability, f: F) {
+        self.root.register_with_capability(name, cap, f);
+    }
+
+    pub fn register_cont<Args, F: IntoContNative<'a, Args>>(&mut self, name: &'a str, f: F) {
+        self.root.register_cont(name, f);
+    }
+
+    pub fn register_cont_with_capability<Args, F: IntoContNative<'a, Args>>(&mut self, name: &'a str, cap: Capability, f: F) {
+        self.root.register_cont_with_capability(name, cap, f);
+    }
+
+    /// Sets the resource limits of every machine started after this.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn get(&self, name: &str)->Option<Continuation<'a>> {
+        self.root.get(name)
+    }
+
+    pub fn root(&self)->&RootScope<'a> {
+        &self.root
+    }
+
+    /// Calls `name` like `(name args...)` would: a return continuation is passed as the first
+    /// argument, and whatever is applied to it is returned.
+    pub fn call<A: IntoArgs<'a>>(&self, name: &str, args: A)->Result<Data<'a>> {
+        self.start(name, args)?.run()
+    }
+
+    /// Jumps to `name` like `(apply name args...)` would, without a return continuation.
+    pub fn apply<A: IntoArgs<'a>>(&self, name: &str, args: A)->Result<Data<'a>> {
+        self.start_apply(name, args)?.run()
+    }
+
+    /// Like `call`, but returns a `Machine` to be stepped by the host instead of running to
+    /// completion.
+    pub fn start<A: IntoArgs<'a>>(&self, name: &str, args: A)->Result<Machine<'_, 'a>> {
+        let mut data_args = vec![Data::Continuation(Continuation::Return)];
+        data_args.extend(args.into_args());
+
+        return self.start_apply(name, data_args);
+    }
+
+    /// Continues a program that yielded to the host, with `reply` as the result of `yield-host`.
+    pub fn resume(&self, resumer: Resumer<'a>, reply: Data<'a>)->Machine<'_, 'a> {
+        let mut machine = resumer.resume(&self.root, reply);
+        machine.set_limits(self.limits);
+
+        return machine;
+    }
+
+    /// Like `apply`, but returns a `Machine` to be stepped by the host.
+    pub fn start_apply<A: IntoArgs<'a>>(&self, name: &str, args: A)->Result<Machine<'_, 'a>> {
+        let Some(cont) = self.root.get(name) else {
+            bail!("No continuation named `{name}`");
+        };
+
+        let mut machine = Machine::new(&self.root, cont, args.into_args());
+        machine.set_limits(self.limits);
+
+        return Ok(machine);
+    }
+}
+
+
+/// Arguments for `Interpreter::call` and `Interpreter::apply`.
+pub trait IntoArgs<'a> {
+    fn into_args(self)->Vec<Data<'a>>;
+}
+impl<'a> IntoArgs<'a> for Vec<Data<'a>> {
+    fn into_args(self)->Vec<Data<'a>> {self}
+}
+
+macro_rules! impl_into_args {
+    ($($arg:ident),*)=>{
+        impl<'a, $($arg: IntoData<'a>),*> IntoArgs<'a> for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self)->Vec<Data<'a>> {
+                let ($($arg,)*) = self;
+                return vec![$($arg.into_data()),*];
+            }
+        }
+    };
+}
+
+impl_into_args!();
+impl_into_args!(A);
+impl_into_args!(A, B);
+impl_into_args!(A, B, C);
+impl_into_args!(A, B, C, D);
+impl_into_args!(A, B, C, D, E);
+impl_into_args!(A, B, C, D, E, G);
@@ -0,0 +1,41 @@
+pub use eval::*;
+pub use arena::*;
+pub use machine::*;
+pub use limits::*;
+pub use capability::*;
+pub use exception::*;
+pub use threads::*;
+pub use channel::*;
+pub use engine::*;
+pub use generator::*;
+pub use amb::*;
+pub use logic::*;
+pub use symbol::*;
+pub use native::*;
+pub use interpreter::*;
+pub use continual_macros::native_module;
+
+extern crate self as continual;
+
+
+pub mod parser;
+pub mod ast;
+pub mod syntax;
+pub mod builtins;
+mod eval;
+mod arena;
+mod desugar;
+mod exhaustive;
+mod machine;
+mod limits;
+mod capability;
+mod exception;
+mod threads;
+mod channel;
+mod engine;
+mod generator;
+mod amb;
+mod logic;
+mod symbol;
+mod native;
+mod interpreter;
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    mem,
+    rc::Rc,
+    time::Duration,
+};
+use crate::{
+    ChannelState,
+    Choice,
+    GenState,
+    Goal,
+    Continuation,
+    Data,
+    Env,
+    Frame,
+    Frames,
+    Prompts,
+    Scheduler,
+};
+
+
+/// How many reductions happen between the more expensive time and data size checks.
+pub(crate) const CHECK_INTERVAL: u64 = 256;
+
+
+/// Resource limits for running untrusted scripts. `None` means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// The maximum number of reductions.
+    pub steps: Option<u64>,
+    /// The maximum time spent running, not counting the time the host spends between `step`s.
+    pub time: Option<Duration>,
+    /// The maximum size of all data reachable by the program, measured in values plus string bytes.
+    pub data_size: Option<usize>,
+    /// The maximum length of any string created by a native.
+    pub string_length: Option<usize>,
+    /// The maximum number of frames waiting for a value.
+    pub depth: Option<usize>,
+}
+
+/// The error returned when a script goes over one of its `Limits`. Can be told apart from other
+/// errors with `anyhow::Error::downcast_ref`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Steps(u64),
+    Time(Duration),
+    DataSize(usize),
+    StringLength(usize),
+    Depth(usize),
+}
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Steps(max)=>write!(f, "Limit exceeded: more than {max} steps"),
+            Self::Time(max)=>write!(f, "Limit exceeded: ran for more than {max:?}"),
+            Self::DataSize(max)=>write!(f, "Limit exceeded: more than {max} units of live data"),
+            Self::StringLength(max)=>write!(f, "Limit exceeded: string longer than {max} bytes"),
+            Self::Depth(max)=>write!(f, "Limit exceeded: more than {max} frames deep"),
+        }
+    }
+}
+impl Error for LimitExceeded {}
+
+
+/// Measures the size of live data. Shared environments and frames are only counted once.
+#[derive(Default)]
+pub(crate) struct SizeCounter<'b, 'a> {
+    seen: HashSet<*const ()>,
+    todo: Vec<&'b Data<'a>>,
+    pub size: usize,
+}
+impl<'b, 'a> SizeCounter<'b, 'a> {
+    pub fn data(&mut self, data: &'b Data<'a>) {
+        self.todo.push(data);
+        self.drain();
+    }
+
+    pub fn env(&mut self, env: &'b Rc<Env<'a>>) {
+        self.push_env(env);
+        self.drain();
+    }
+
+    pub fn frames(&mut self, frames: &'b Frames<'a>) {
+        self.push_frames(frames);
+        self.drain();
+    }
+
+    pub fn prompts(&mut self, prompts: &'b Prompts<'a>) {
+        self.push_prompts(prompts);
+        self.drain();
+    }
+
+    pub fn continuation(&mut self, cont: &'b Continuation<'a>) {
+        self.push_continuation(cont);
+        self.drain();
+    }
+
+    /// Every parked thread and thread result.
+    pub fn threads(&mut self, threads: &'b Scheduler<'a>) {
+        for parked in threads.parked() {
+            self.push_continuation(&parked.cont);
+            self.push_prompts(&parked.prompts);
+            self.todo.extend(&parked.args);
+        }
+        self.todo.extend(threads.results());
+        self.drain();
+    }
+
+    /// Every choice point and the solutions collected so far.
+    pub fn choices(&mut self, choices: &'b [Choice<'a>]) {
+        for choice in choices {
+            match choice {
+                Choice::Alternatives{cont,prompts,rest}=>{
+                    self.push_continuation(cont);
+                    self.push_prompts(prompts);
+                    self.todo.extend(rest);
+                },
+                Choice::Collect{cont,prompts,results}=>{
+                    self.push_continuation(cont);
+                    self.push_prompts(prompts);
+                    self.todo.extend(results);
+                },
+            }
+        }
+        self.drain();
+    }
+
+    fn goal(&mut self, goal: &'b Goal<'a>) {
+        match goal {
+            Goal::Unify(a,b)=>self.todo.extend([a, b]),
+            Goal::All(goals)|Goal::Any(goals)=>for goal in goals {
+                self.size += 1;
+                self.goal(goal);
+            },
+            Goal::Fresh{cont,args,..}=>{
+                self.push_continuation(cont);
+                self.todo.extend(args);
+            },
+        }
+    }
+
+    fn generator(&mut self, state: &'b GenState<'a>) {
+        match state {
+            GenState::Paused{cont,args}=>{
+                self.push_continuation(cont);
+                self.todo.extend(args);
+            },
+            GenState::Emitted{value,cont}=>{
+                self.push_continuation(cont);
+                self.todo.push(value);
+            },
+            GenState::Running|GenState::Done=>{},
+        }
+        self.drain();
+    }
+
+    /// The buffered values and the waiting threads of a channel.
+    fn channel(&mut self, state: &'b ChannelState<'a>) {
+        self.todo.extend(&state.buffer);
+        for waiter in state.senders.iter().chain(&state.receivers) {
+            self.push_continuation(&waiter.parked.cont);
+            self.push_prompts(&waiter.parked.prompts);
+            self.todo.push(&waiter.value);
+        }
+        self.drain();
+    }
+
+    // uses a work list instead of recursion, since continuations can be nested very deeply
+    fn drain(&mut self) {
+        while let Some(data) = self.todo.pop() {
+            self.size += 1;
+            match data {
+                Data::String(s)=>self.size += s.len(),
+                Data::Symbol(name)=>self.size += name.len(),
+                Data::Error(e)=>{
+                    self.size += e.message.len();
+                    self.todo.push(&e.value);
+                },
+                Data::Continuation(cont)=>self.push_continuation(cont),
+                Data::Engine(engine)=>{
+                    self.push_continuation(&engine.cont);
+                    self.todo.extend(&engine.args);
+                },
+                Data::List(list) if self.seen.insert(Rc::as_ptr(list).cast())=>self.todo.extend(list.iter()),
+                Data::Goal(goal) if self.seen.insert(Rc::as_ptr(goal).cast())=>self.goal(goal),
+                Data::Generator(generator) if self.seen.insert(generator.as_ptr())=>{
+                    let state = generator.state().borrow();
+                    let mut inner = SizeCounter {
+                        seen: mem::take(&mut self.seen),
+                        todo: Vec::new(),
+                        size: 0,
+                    };
+                    inner.generator(&state);
+                    self.seen = inner.seen;
+                    self.size += inner.size;
+                },
+                Data::Channel(ch) if self.seen.insert(ch.as_ptr())=>{
+                    // the contents only live as long as the borrow, so they are counted separately
+                    let state = ch.state().borrow();
+                    let mut inner = SizeCounter {
+                        seen: mem::take(&mut self.seen),
+                        todo: Vec::new(),
+                        size: 0,
+                    };
+                    inner.channel(&state);
+                    self.seen = inner.seen;
+                    self.size += inner.size;
+                },
+                _=>{},
+            }
+        }
+    }
+
+    fn push_env(&mut self, env: &'b Rc<Env<'a>>) {
+        if self.seen.insert(Rc::as_ptr(env).cast()) {
+            self.todo.extend(env.values());
+        }
+    }
+
+    fn push_continuation(&mut self, cont: &'b Continuation<'a>) {
+        match cont {
+            Continuation::Normal{env,frames}=>{
+                self.push_env(env);
+                self.push_frames(frames);
+            },
+            Continuation::Delimited{env,frames,prompts,..}=>{
+                self.push_env(env);
+                self.push_frames(frames);
+                self.push_prompts(prompts);
+            },
+            _=>{},
+        }
+    }
+
+    fn push_prompts(&mut self, prompts: &'b Prompts<'a>) {
+        for (env, frames) in prompts.iter() {
+            self.push_env(env);
+            self.push_frames(frames);
+        }
+    }
+
+    fn push_frames(&mut self, frames: &'b Frames<'a>) {
+        for (ptr, frame) in frames.nodes() {
+            if !self.seen.insert(ptr) {
+                return;
+            }
+
+            self.size += 1;
+            match frame {
+                Frame::Args{callee,done,..}=>{
+                    self.todo.push(callee);
+                    self.todo.extend(done);
+                },
+                Frame::Catch{env,..}|Frame::Wind{env,..}=>self.push_env(env),
+                Frame::Value(data)|Frame::Param{value:data,..}|Frame::Guard{value:data,..}=>self.todo.push(data),
+                Frame::MarkArgs{key,..}=>self.todo.extend(key),
+                Frame::Mark{key,value}=>self.todo.extend([key, value]),
+                Frame::Rewind{env,frames,values}=>{
+                    self.push_env(env);
+                    self.push_frames(frames);
+                    self.todo.extend(values);
+                },
+                Frame::Rethrow(e)=>self.todo.push(&e.value),
+                Frame::Then(cont)=>self.push_continuation(cont),
+                Frame::Perform{done,..}|Frame::Select{done,..}=>self.todo.extend(done),
+                _=>{},
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    collections::VecDeque,
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+    },
+    rc::Rc,
+};
+use crate::{
+    Continuation,
+    ContRet,
+    Data,
+    ErrorKind,
+    FromData,
+    Native,
+    ScriptError,
+};
+
+
+/// A logic variable, bound by unification during a `run`. Compared by identity.
+#[derive(Clone, Default)]
+pub struct LVar(Rc<()>);
+impl LVar {
+    pub fn new()->Self {
+        LVar::default()
+    }
+
+    fn as_ptr(&self)->*const () {
+        Rc::as_ptr(&self.0)
+    }
+}
+impl Debug for LVar {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "LVar({:p})", self.as_ptr())
+    }
+}
+impl PartialEq for LVar {
+    fn eq(&self, other: &Self)->bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A goal for `run` to find the ways of satisfying.
+#[derive(Debug, PartialEq)]
+pub enum Goal<'a> {
+    /// Succeeds if the two values unify.
+    Unify(Data<'a>, Data<'a>),
+    /// Succeeds if every goal does.
+    All(Vec<Rc<Goal<'a>>>),
+    /// Succeeds once for every way any of the goals does. The goals are searched in turns, so one
+    /// that never stops succeeding doesn't keep the others from running.
+    Any(Vec<Rc<Goal<'a>>>),
+    /// Calls `cont` with `args` followed by `count` fresh variables, and succeeds like the goal it
+    /// returns. The call only happens when the search gets to it, so relations can be recursive.
+    Fresh {
+        count: usize,
+        cont: Continuation<'a>,
+        args: Vec<Data<'a>>,
+    },
+}
+/// A goal, or a list of goals that all have to succeed.
+impl<'a> FromData<'a> for Rc<Goal<'a>> {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Goal(goal)=>Ok(goal),
+            Data::List(goals)=>{
+                let goals = goals.iter()
+                    .cloned()
+                    .map(Self::from_data)
+                    .collect::<Result<_>>()?;
+                Ok(Rc::new(Goal::All(goals)))
+            },
+            d=>bail!("Expected goal, but got {}", d.type_name()),
+        }
+    }
+}
+
+
+/// The values bound to logic variables, newest first.
+#[derive(Debug, Clone, Default)]
+struct Subst<'a>(Option<Rc<Binding<'a>>>);
+
+#[derive(Debug)]
+struct Binding<'a> {
+    var: LVar,
+    value: Data<'a>,
+    next: Subst<'a>,
+}
+
+impl<'a> Subst<'a> {
+    fn lookup(&self, var: &LVar)->Option<&Data<'a>> {
+        let mut node = self.0.as_deref();
+        while let Some(binding) = node {
+            if binding.var == *var {
+                return Some(&binding.value);
+            }
+            node = binding.next.0.as_deref();
+        }
+
+        return None;
+    }
+
+    /// Follows variables to what they are bound to, stopping at a value or an unbound variable.
+    fn walk(&self, data: &Data<'a>)->Data<'a> {
+        let mut data = data.clone();
+        while let Data::LVar(var) = &data {
+            match self.lookup(var) {
+                Some(value)=>data = value.clone(),
+                None=>break,
+            }
+        }
+
+        return data;
+    }
+
+    fn extend(&self, var: LVar, value: Data<'a>)->Self {
+        Subst(Some(Rc::new(Binding {var, value, next: self.clone()})))
+    }
+
+    /// Whether binding `var` to `data` would make a value that contains itself.
+    fn occurs(&self, var: &LVar, data: &Data<'a>)->bool {
+        match self.walk(data) {
+            Data::LVar(v)=>v == *var,
+            Data::List(items)=>items.iter().any(|item|self.occurs(var, item)),
+            _=>false,
+        }
+    }
+
+    fn unify(&self, a: &Data<'a>, b: &Data<'a>)->Option<Self> {
+        match (self.walk(a), self.walk(b)) {
+            (Data::LVar(a),Data::LVar(b)) if a == b=>Some(self.clone()),
+            (Data::LVar(var),value)|(value,Data::LVar(var))=>{
+                if self.occurs(&var, &value) {
+                    return None;
+                }
+                Some(self.extend(var, value))
+            },
+            (Data::List(a),Data::List(b))=>{
+                if a.len() != b.len() {
+                    return None;
+                }
+                let mut subst = self.clone();
+                for (a, b) in a.iter().zip(b.iter()) {
+                    subst = subst.unify(a, b)?;
+                }
+                Some(subst)
+            },
+            (a,b)=>(a == b).then(||self.clone()),
+        }
+    }
+
+    /// Replaces every bound variable in `data` with its value, and replaces the unbound ones with
+    /// the symbols `_.0`, `_.1` and so on in the order they appear, so they can't be mistaken for
+    /// strings in the answer.
+    fn reify(&self, data: &Data<'a>, names: &mut Vec<LVar>)->Data<'a> {
+        match self.walk(data) {
+            Data::LVar(var)=>{
+                let i = match names.iter().position(|v|*v == var) {
+                    Some(i)=>i,
+                    None=>{
+                        names.push(var);
+                        names.len() - 1
+                    },
+                };
+                Data::Symbol(format!("_.{i}").into())
+            },
+            Data::List(items)=>{
+                Data::List(Rc::new(items.iter().map(|item|self.reify(item, names)).collect()))
+            },
+            data=>data,
+        }
+    }
+}
+
+
+/// A path through the search: the goals left to satisfy, and the bindings made so far.
+#[derive(Debug, Clone)]
+struct Branch<'a> {
+    /// The next goal is last.
+    goals: Vec<Rc<Goal<'a>>>,
+    subst: Subst<'a>,
+}
+
+/// A `run` in progress. Branches take turns, so every answer is found eventually even if some
+/// branches never end.
+#[derive(Debug, Clone)]
+pub struct Search<'a> {
+    ret: Continuation<'a>,
+    query: LVar,
+    limit: Option<usize>,
+    answers: Vec<Data<'a>>,
+    queue: VecDeque<Branch<'a>>,
+}
+impl<'a> Search<'a> {
+    /// Finds up to `limit` values of `query` that satisfy `goal`, or all of them if there is no
+    /// limit. The answers are returned to `ret` as a list.
+    pub fn new(ret: Continuation<'a>, query: LVar, goal: Rc<Goal<'a>>, limit: Option<usize>)->Self {
+        let branch = Branch {
+            goals: vec![goal],
+            subst: Subst::default(),
+        };
+
+        return Search {
+            ret,
+            query,
+            limit,
+            answers: Vec::new(),
+            queue: VecDeque::from([branch]),
+        };
+    }
+
+    /// Searches until the answers are found or the search has to call into Continual for a `Fresh`
+    /// goal, which continues the search once it returns.
+    pub fn run(mut self)->Result<ContRet<'a>> {
+        while let Some(mut branch) = self.queue.pop_front() {
+            if self.limit.is_some_and(|limit|self.answers.len() >= limit) {
+                break;
+            }
+
+            while let Some(goal) = branch.goals.pop() {
+                match &*goal {
+                    Goal::Unify(a,b)=>match branch.subst.unify(a, b) {
+                        Some(subst)=>branch.subst = subst,
+                        None=>break,
+                    },
+                    Goal::All(goals)=>branch.goals.extend(goals.iter().rev().cloned()),
+                    Goal::Any(goals)=>{
+                        for goal in goals {
+                            let mut branch = branch.clone();
+                            branch.goals.push(goal.clone());
+                            self.queue.push_back(branch);
+                        }
+                        break;
+                    },
+                    Goal::Fresh{count,cont,args}=>{
+                        let mut args = args.clone();
+                        args.extend((0..*count).map(|_|Data::LVar(LVar::new())));
+                        args.insert(0, Data::Continuation(self.resume(branch)));
+
+                        return Ok(ContRet::Apply(cont.clone(), args));
+                    },
+                }
+
+                if branch.goals.is_empty() {
+                    let answer = branch.subst.reify(&Data::LVar(self.query.clone()), &mut Vec::new());
+                    self.answers.push(answer);
+                    break;
+                }
+            }
+        }
+
+        return Ok(ContRet::Apply(self.ret, vec![Data::List(Rc::new(self.answers))]));
+    }
+
+    /// The return continuation of a `Fresh` goal's call, which puts the goal it returns at the end
+    /// of the queue in place of the `Fresh` goal.
+    fn resume(self, branch: Branch<'a>)->Continuation<'a> {
+        let native = Native::new(move|_, args|{
+            let mut args = args.into_iter();
+            let (Some(goal), None) = (args.next(), args.next()) else {
+                bail!(ScriptError::new(ErrorKind::Arity, "Expected a single goal from a `fresh` continuation"));
+            };
+            let goal = match Rc::<Goal>::from_data(goal) {
+                Ok(goal)=>goal,
+                Err(e)=>bail!(ScriptError::new(ErrorKind::Type, format!("A `fresh` continuation returned a bad goal: {e}"))),
+            };
+
+            let mut search = self.clone();
+            let mut branch = branch.clone();
+            branch.goals.push(goal);
+            search.queue.push_back(branch);
+
+            return search.run();
+        });
+
+        return Continuation::Native(native);
+    }
+}
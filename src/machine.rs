@@ -0,0 +1,2067 @@
+use anyhow::{
+    Error,
+    Result,
+    bail,
+};
+use std::{
+    cell::Cell,
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+    },
+    mem,
+    rc::Rc,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::{
+    ast::*,
+    limits::*,
+    threads::*,
+    channel::*,
+    engine::*,
+    generator::*,
+    amb::*,
+    exception::*,
+    parser::Parser,
+    syntax::Syntax,
+    check_links,
+    CodeArena,
+    Capabilities,
+    Continuation,
+    ContRet,
+    Data,
+    Native,
+    RootScope,
+};
+
+
+/// The variables visible to the expression being evaluated. Shared between captured continuations
+/// and copied on write, so re-entering a continuation sees the variables as they were when it was
+/// captured.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Env<'a> {
+    scopes: Vec<HashMap<&'a str, Data<'a>>>,
+    vars: HashMap<&'a str, Data<'a>>,
+    /// The natives the code running in these scopes may look up.
+    caps: Capabilities,
+}
+impl<'a> Env<'a> {
+    pub fn new(vars: HashMap<&'a str, Data<'a>>, caps: Capabilities)->Self {
+        Env {
+            scopes: Vec::new(),
+            vars,
+            caps,
+        }
+    }
+
+    pub fn get(&self, name: &str)->Option<&Data<'a>> {
+        if let Some(data) = self.vars.get(name) {
+            return Some(data);
+        }
+        for scope in self.scopes.iter().rev() {
+            if let Some(data) = scope.get(name) {
+                return Some(data);
+            }
+        }
+
+        return None;
+    }
+
+    pub fn set(&mut self, name: &'a str, data: Data<'a>) {
+        self.vars.insert(name, data);
+    }
+
+    /// Every value in every scope, including shadowed ones.
+    pub fn values(&self)->impl Iterator<Item = &Data<'a>> {
+        self.scopes.iter()
+            .chain(std::iter::once(&self.vars))
+            .flat_map(HashMap::values)
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(mem::take(&mut self.vars));
+    }
+
+    fn pop_scope(&mut self) {
+        self.vars = self.scopes.pop().unwrap_or_default();
+    }
+}
+
+
+/// Something waiting for the value of the expression being evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame<'a> {
+    /// The remaining expressions of a block.
+    Block(&'a [Expr<'a>]),
+    /// Waiting for the continuation of a `Call` or `Apply`. `tail` is set for `Apply`, which does
+    /// not pass the current continuation. `site` is the whole call, for error locations.
+    Callee {
+        site: &'a Expr<'a>,
+        args: &'a [Expr<'a>],
+        tail: bool,
+    },
+    /// Waiting for the arguments of a `Call` or `Apply`.
+    Args {
+        site: &'a Expr<'a>,
+        callee: Data<'a>,
+        done: Vec<Data<'a>>,
+        rest: &'a [Expr<'a>],
+        tail: bool,
+    },
+    If {
+        expr: &'a Expr<'a>,
+        default: Option<&'a Expr<'a>>,
+    },
+    SetVar(&'a str),
+    /// Waiting for the values of a `receive`. Applying a continuation to several values binds them
+    /// all, and returning a value normally binds just the one.
+    Receive(&'a [&'a str]),
+    /// Pops the scope pushed by `letcc`.
+    PopScope,
+    /// The handler of a `try`, with the variables it runs in.
+    Catch {
+        var: &'a str,
+        handler: &'a [Expr<'a>],
+        env: Rc<Env<'a>>,
+    },
+    /// Waiting for the `before` of a `dynamic-wind`.
+    WindBody {
+        before: &'a Expr<'a>,
+        body: &'a Expr<'a>,
+        after: &'a Expr<'a>,
+    },
+    /// The body of a `dynamic-wind` is running. `before` and `after` run in `env` when a
+    /// continuation jumps in or out.
+    Wind {
+        before: &'a Expr<'a>,
+        after: &'a Expr<'a>,
+        env: Rc<Env<'a>>,
+    },
+    /// Waiting for the value of a `parameterize`.
+    Parameterize {
+        site: &'a Expr<'a>,
+        name: &'a str,
+        body: &'a [Expr<'a>],
+    },
+    /// The body of a `parameterize` is running with `name` bound to `value`.
+    Param {
+        name: &'a str,
+        value: Data<'a>,
+    },
+    /// Waiting for the key and then the value of a `with-cont-mark`.
+    MarkArgs {
+        key: Option<Data<'a>>,
+        value: &'a Expr<'a>,
+        body: &'a [Expr<'a>],
+    },
+    /// A continuation mark. Consecutive marks all belong to the same frame, and have different keys.
+    Mark {
+        key: Data<'a>,
+        value: Data<'a>,
+    },
+    /// Waiting for the default of an optional parameter. The defaults in `rest` come after it.
+    Default {
+        name: &'a str,
+        rest: &'a [(&'a str, Expr<'a>)],
+        body: &'a [Expr<'a>],
+    },
+    /// Ignores the value, then evaluates the defaults of the optional parameters that weren't given
+    /// and runs the body. Starts a generator, whose arguments are bound when it is made.
+    Body {
+        optional: &'a [(&'a str, Expr<'a>)],
+        body: &'a [Expr<'a>],
+    },
+    /// Ignores the value and returns this one instead.
+    Value(Data<'a>),
+    /// Ignores the value and returns these values instead.
+    Values(Vec<Data<'a>>),
+    /// Returns the value with these variables. Restores the caller's variables when a composable
+    /// continuation returns to it.
+    Env(Rc<Env<'a>>),
+    /// Continues a jump that stopped to run a `before` or `after`.
+    Rewind {
+        env: Rc<Env<'a>>,
+        frames: Frames<'a>,
+        values: Vec<Data<'a>>,
+    },
+    /// Continues throwing after an `after` ran.
+    Rethrow(Rc<Exception<'a>>),
+    /// Waiting for the arguments of a `perform`.
+    Perform {
+        site: &'a Expr<'a>,
+        effect: &'a str,
+        done: Vec<Data<'a>>,
+        rest: &'a [Expr<'a>],
+    },
+    /// Waiting for the channels and values of a `select`.
+    Select {
+        site: &'a Expr<'a>,
+        clauses: &'a [SelectClause<'a>],
+        default: Option<&'a [Expr<'a>]>,
+        done: Vec<Data<'a>>,
+    },
+    /// Runs the `select` clause that was ready, with the value it received.
+    Selected(&'a SelectClause<'a>),
+    /// Waiting for the value of a `match`.
+    Match {
+        site: &'a Expr<'a>,
+        clauses: &'a [MatchClause<'a>],
+    },
+    /// Waiting for the guard of a `match` clause, which runs `body` if it is true or tries the
+    /// clauses in `rest` if it isn't.
+    Guard {
+        site: &'a Expr<'a>,
+        body: &'a [Expr<'a>],
+        rest: &'a [MatchClause<'a>],
+        value: Data<'a>,
+    },
+    /// Applies the continuation to the value.
+    Then(Continuation<'a>),
+    /// The bottom of a call. A value returned to it ends the computation up to the nearest `reset`.
+    Barrier,
+    /// Stops the machine once every `dynamic-wind` has been left.
+    Halt,
+}
+impl<'a> Frame<'a> {
+    /// Whether a tail call keeps this frame. Calls can't leave a `try`, `dynamic-wind` or
+    /// `parameterize` body by tail calling, and tail calls keep the marks of the frame.
+    fn is_floor(&self)->bool {
+        matches!(self, Frame::Barrier|Frame::Catch{..}|Frame::Wind{..}|Frame::Param{..}|Frame::Mark{..})
+    }
+}
+
+/// A persistent stack of frames, so capturing the current continuation is just a pointer copy.
+///
+/// A call pushes a `Barrier` and runs the callee on top of the caller's frames. The stack only
+/// reaches down to the nearest `reset`; the frames below it are kept in `Prompts`.
+#[derive(Clone, Default)]
+pub struct Frames<'a> {
+    head: Option<Rc<FrameNode<'a>>>,
+}
+struct FrameNode<'a> {
+    frame: Frame<'a>,
+    next: Frames<'a>,
+    len: usize,
+    /// The nearest floor below this node.
+    floor: Option<Rc<FrameNode<'a>>>,
+    /// The nearest `Wind` below this node.
+    wind: Option<Rc<FrameNode<'a>>>,
+}
+impl<'a> Frames<'a> {
+    pub fn is_empty(&self)->bool {
+        self.head.is_none()
+    }
+
+    pub fn len(&self)->usize {
+        self.head.as_ref().map_or(0, |node|node.len)
+    }
+
+    /// The frames of the current call, up to and including its `Barrier`, or the innermost `try`,
+    /// `dynamic-wind` or `parameterize` body in it. This is what is left after a tail call.
+    pub fn floor(&self)->Self {
+        match &self.head {
+            Some(node) if node.frame.is_floor()=>self.clone(),
+            Some(node)=>Frames {head: node.floor.clone()},
+            None=>Frames::default(),
+        }
+    }
+
+    pub fn push(&mut self, frame: Frame<'a>) {
+        let next = mem::take(self);
+        let len = next.len() + 1;
+        let floor = next.floor().head.clone();
+        let wind = next.winds().next().cloned();
+        self.head = Some(Rc::new(FrameNode {frame, next, len, floor, wind}));
+    }
+
+    /// A stack that stops the machine when returned to.
+    fn halt()->Self {
+        let mut frames = Frames::default();
+        frames.push(Frame::Halt);
+
+        return frames;
+    }
+
+    /// The nodes of every `Wind` frame, innermost first.
+    fn winds(&self)->impl Iterator<Item = &Rc<FrameNode<'a>>> {
+        let mut cur = match &self.head {
+            Some(node) if matches!(node.frame, Frame::Wind{..})=>Some(node),
+            Some(node)=>node.wind.as_ref(),
+            None=>None,
+        };
+        std::iter::from_fn(move||{
+            let node = cur?;
+            cur = node.wind.as_ref();
+            return Some(node);
+        })
+    }
+
+    pub fn pop(&mut self)->Option<Frame<'a>> {
+        let node = self.head.take()?;
+        match Rc::try_unwrap(node) {
+            Ok(mut node)=>{
+                self.head = node.next.head.take();
+                return Some(node.frame);
+            },
+            Err(node)=>{
+                *self = node.next.clone();
+                return Some(node.frame.clone());
+            },
+        }
+    }
+
+    pub fn iter(&self)->impl Iterator<Item = &Frame<'a>> {
+        self.nodes().map(|(_, frame)|frame)
+    }
+
+    /// Iterates the frames along with the address of the node holding them, so shared tails can be
+    /// recognized.
+    pub(crate) fn nodes(&self)->impl Iterator<Item = (*const (), &Frame<'a>)> {
+        let mut cur = self.head.as_ref();
+        std::iter::from_fn(move||{
+            let node = cur?;
+            cur = node.next.head.as_ref();
+            return Some((Rc::as_ptr(node).cast(), &node.frame));
+        })
+    }
+}
+impl<'a> Drop for Frames<'a> {
+    // dropping a long chain recursively can overflow the stack
+    fn drop(&mut self) {
+        let mut cur = self.head.take();
+        while let Some(node) = cur {
+            match Rc::try_unwrap(node) {
+                Ok(mut node)=>cur = node.next.head.take(),
+                Err(_)=>break,
+            }
+        }
+    }
+}
+/// Frames are compared by identity: two continuations are equal if they continue the same frames.
+impl<'a> PartialEq for Frames<'a> {
+    fn eq(&self, other: &Self)->bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b))=>Rc::ptr_eq(a, b),
+            (None, None)=>true,
+            _=>false,
+        }
+    }
+}
+impl<'a> Debug for Frames<'a> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+
+/// What pushed a prompt.
+#[derive(Clone)]
+enum Delimiter<'a> {
+    /// A `reset`, or the caller of a continuation captured by `shift`.
+    Reset,
+    /// A `handle`, with its effect handlers.
+    Handle(&'a [Handler<'a>]),
+    /// An engine, with the step it runs out of fuel at. Captured prompts keep the fuel left instead.
+    Engine(u64),
+    /// A generator computing its next value for `op`.
+    Generator(Generator<'a>, GenOp<'a>),
+}
+
+/// The variables, frames and delimiter of a popped prompt.
+type SavedPrompt<'a> = (Rc<Env<'a>>, Frames<'a>, Delimiter<'a>);
+
+/// The frames and variables saved by each enclosing `reset`, `handle`, engine or generator,
+/// innermost first. They are resumed when the frames above them run out.
+#[derive(Clone, Default)]
+pub struct Prompts<'a> {
+    head: Option<Rc<Prompt<'a>>>,
+}
+struct Prompt<'a> {
+    env: Rc<Env<'a>>,
+    frames: Frames<'a>,
+    delimiter: Delimiter<'a>,
+    next: Prompts<'a>,
+    /// The number of frames in this prompt and the ones below it.
+    depth: usize,
+    /// The earliest step any engine in this prompt or the ones below it runs out of fuel at.
+    deadline: Option<u64>,
+}
+impl<'a> Prompts<'a> {
+    pub fn is_empty(&self)->bool {
+        self.head.is_none()
+    }
+
+    /// The variables saved by the nearest prompt.
+    fn env(&self)->Option<Rc<Env<'a>>> {
+        self.head.as_ref().map(|prompt|prompt.env.clone())
+    }
+
+    /// The number of frames saved by all prompts.
+    pub fn depth(&self)->usize {
+        self.head.as_ref().map_or(0, |prompt|prompt.depth)
+    }
+
+    /// The earliest step an engine runs out of fuel at.
+    pub fn deadline(&self)->Option<u64> {
+        self.head.as_ref().and_then(|prompt|prompt.deadline)
+    }
+
+    /// Pushes the prompt of a `reset`, or of a `handle` if there are any handlers.
+    fn push(&mut self, env: Rc<Env<'a>>, frames: Frames<'a>, handlers: &'a [Handler<'a>]) {
+        let delimiter = match handlers {
+            []=>Delimiter::Reset,
+            handlers=>Delimiter::Handle(handlers),
+        };
+        self.push_prompt(env, frames, delimiter);
+    }
+
+    fn push_prompt(&mut self, env: Rc<Env<'a>>, frames: Frames<'a>, delimiter: Delimiter<'a>) {
+        let next = mem::take(self);
+        let depth = next.depth() + frames.len();
+        let deadline = match (&delimiter, next.deadline()) {
+            (Delimiter::Engine(a), Some(b))=>Some(b.min(*a)),
+            (Delimiter::Engine(a), None)=>Some(*a),
+            (_, b)=>b,
+        };
+        self.head = Some(Rc::new(Prompt {env, frames, delimiter, next, depth, deadline}));
+    }
+
+    fn pop(&mut self)->Option<(Rc<Env<'a>>, Frames<'a>)> {
+        self.pop_prompt().map(|(env, frames, _)|(env, frames))
+    }
+
+    fn pop_prompt(&mut self)->Option<SavedPrompt<'a>> {
+        let prompt = self.head.take()?;
+        match Rc::try_unwrap(prompt) {
+            Ok(mut prompt)=>{
+                self.head = prompt.next.head.take();
+                return Some((prompt.env, prompt.frames, prompt.delimiter));
+            },
+            Err(prompt)=>{
+                *self = prompt.next.clone();
+                return Some((prompt.env.clone(), prompt.frames.clone(), prompt.delimiter.clone()));
+            },
+        }
+    }
+
+    /// Moves the `n` innermost prompts into a list of their own, for a captured continuation. Engine
+    /// deadlines become the fuel left at step `now`.
+    fn take(&mut self, n: usize, now: u64)->Self {
+        let mut taken = Vec::new();
+        for _ in 0..n {
+            taken.push(self.pop_prompt().unwrap());
+        }
+
+        let mut prompts = Prompts::default();
+        for (env, frames, delimiter) in taken.into_iter().rev() {
+            let delimiter = match delimiter {
+                Delimiter::Engine(deadline)=>Delimiter::Engine(deadline.saturating_sub(now)),
+                delimiter=>delimiter,
+            };
+            prompts.push_prompt(env, frames, delimiter);
+        }
+
+        return prompts;
+    }
+
+    /// Pushes the prompts taken with `take`, turning the fuel left back into deadlines.
+    fn extend(&mut self, mut taken: Self, now: u64) {
+        let mut inner = Vec::new();
+        while let Some(prompt) = taken.pop_prompt() {
+            inner.push(prompt);
+        }
+        for (env, frames, delimiter) in inner.into_iter().rev() {
+            let delimiter = match delimiter {
+                Delimiter::Engine(fuel)=>Delimiter::Engine(now.saturating_add(fuel)),
+                delimiter=>delimiter,
+            };
+            self.push_prompt(env, frames, delimiter);
+        }
+    }
+
+    /// How many prompts are above the outermost engine that ran out of fuel by step `now`.
+    fn expired(&self, now: u64)->Option<usize> {
+        let mut expired = None;
+        let mut cur = self.head.as_ref();
+        let mut i = 0;
+        while let Some(prompt) = cur {
+            if prompt.deadline.is_none_or(|deadline|deadline > now) {
+                break;
+            }
+            if let Delimiter::Engine(deadline) = prompt.delimiter {
+                if deadline <= now {
+                    expired = Some(i);
+                }
+            }
+            cur = prompt.next.head.as_ref();
+            i += 1;
+        }
+
+        return expired;
+    }
+
+    /// How many prompts are above the innermost generator.
+    fn generator(&self)->Option<usize> {
+        let mut cur = self.head.as_ref();
+        let mut i = 0;
+        while let Some(prompt) = cur {
+            if let Delimiter::Generator(..) = prompt.delimiter {
+                return Some(i);
+            }
+            cur = prompt.next.head.as_ref();
+            i += 1;
+        }
+
+        return None;
+    }
+
+    /// The handlers of each prompt, innermost first.
+    fn handlers(&self)->impl Iterator<Item = &'a [Handler<'a>]> + '_ {
+        let mut cur = self.head.as_ref();
+        std::iter::from_fn(move||{
+            let prompt = cur?;
+            cur = prompt.next.head.as_ref();
+            match prompt.delimiter {
+                Delimiter::Handle(handlers)=>return Some(handlers),
+                _=>return Some(&[]),
+            }
+        })
+    }
+
+    pub(crate) fn iter(&self)->impl Iterator<Item = (&Rc<Env<'a>>, &Frames<'a>)> {
+        let mut cur = self.head.as_ref();
+        std::iter::from_fn(move||{
+            let prompt = cur?;
+            cur = prompt.next.head.as_ref();
+            return Some((&prompt.env, &prompt.frames));
+        })
+    }
+}
+impl<'a> Drop for Prompts<'a> {
+    fn drop(&mut self) {
+        let mut cur = self.head.take();
+        while let Some(prompt) = cur {
+            match Rc::try_unwrap(prompt) {
+                Ok(mut prompt)=>cur = prompt.next.head.take(),
+                Err(_)=>break,
+            }
+        }
+    }
+}
+impl<'a> PartialEq for Prompts<'a> {
+    fn eq(&self, other: &Self)->bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b))=>Rc::ptr_eq(a, b),
+            (None, None)=>true,
+            _=>false,
+        }
+    }
+}
+impl<'a> Debug for Prompts<'a> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        f.debug_list().entries(self.iter().map(|(_, frames)|frames)).finish()
+    }
+}
+
+
+#[derive(Debug)]
+pub enum Status<'a> {
+    /// The step budget ran out before the program finished.
+    Running,
+    Finished(Data<'a>),
+    /// The program called `yield-host`. The machine is halted, and the program continues once the
+    /// `Resumer` is resumed with a reply.
+    Suspended(Data<'a>, Resumer<'a>),
+    Error(Error),
+}
+
+/// The continuation of a `yield-host` call, along with the program's other threads. Can be resumed
+/// at any later time, any number of times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resumer<'a> {
+    cont: Continuation<'a>,
+    prompts: Prompts<'a>,
+    threads: Box<Scheduler<'a>>,
+    choices: Vec<Choice<'a>>,
+}
+impl<'a> Resumer<'a> {
+    /// Creates a machine that continues the program with `reply` as the result of `yield-host`.
+    pub fn resume<'r>(self, root: &'r RootScope<'a>, reply: Data<'a>)->Machine<'r, 'a> {
+        let mut machine = Machine::new(root, self.cont, vec![reply]);
+        machine.prompts = self.prompts;
+        machine.threads = *self.threads;
+        machine.choices = self.choices;
+
+        return machine;
+    }
+
+    pub fn continuation(&self)->&Continuation<'a> {
+        &self.cont
+    }
+}
+
+/// The outcome of a single reduction.
+enum Step<'a> {
+    Continue,
+    Finished(Data<'a>),
+    Suspended(Data<'a>, Continuation<'a>),
+}
+
+enum State<'a> {
+    Eval(&'a Expr<'a>),
+    Return(Data<'a>),
+    /// Returns any number of values other than one. Frames that pass a value straight through pass
+    /// all of them on to a `receive` below them, and any other frame only sees the first.
+    Values(Vec<Data<'a>>),
+    Apply(Continuation<'a>, Vec<Data<'a>>),
+    Halted,
+}
+
+/// A resumable evaluation. Every call to `step` performs a bounded number of reductions, so a host
+/// can interleave Continual code with its own work.
+pub struct Machine<'r, 'a> {
+    root: &'r RootScope<'a>,
+    state: State<'a>,
+    env: Rc<Env<'a>>,
+    frames: Frames<'a>,
+    prompts: Prompts<'a>,
+    threads: Scheduler<'a>,
+    /// The choice points left by `amb` and `all-solutions`, most recent last. Shared by every thread.
+    choices: Vec<Choice<'a>>,
+    /// The call or variable being evaluated, for error locations.
+    site: Option<&'a Expr<'a>>,
+    steps: u64,
+    limits: Limits,
+    /// Time spent running in previous `step`s.
+    elapsed: Duration,
+    slice_start: Instant,
+}
+impl<'r, 'a> Machine<'r, 'a> {
+    /// Creates a machine that applies `cont` to `args` when stepped.
+    pub fn new(root: &'r RootScope<'a>, cont: Continuation<'a>, args: Vec<Data<'a>>)->Self {
+        Machine {
+            root,
+            state: State::Apply(cont, args),
+            env: Rc::default(),
+            frames: Frames::default(),
+            prompts: Prompts::default(),
+            threads: Scheduler::default(),
+            choices: Vec::new(),
+            site: None,
+            steps: 0,
+            limits: Limits::default(),
+            elapsed: Duration::ZERO,
+            slice_start: Instant::now(),
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self)->&Limits {
+        &self.limits
+    }
+
+    /// The time spent running so far, not counting the time between `step`s.
+    pub fn time_used(&self)->Duration {
+        self.elapsed + self.slice_start.elapsed()
+    }
+
+    /// The total number of reductions performed so far.
+    pub fn steps(&self)->u64 {
+        self.steps
+    }
+
+    pub fn is_halted(&self)->bool {
+        matches!(self.state, State::Halted)
+    }
+
+    /// Performs up to `n` reductions. Once the machine returns `Finished` or `Error` it is halted and
+    /// further steps are errors.
+    pub fn step(&mut self, n: usize)->Status<'a> {
+        self.slice_start = Instant::now();
+        let status = self.step_inner(n);
+        self.elapsed += self.slice_start.elapsed();
+
+        return status;
+    }
+
+    fn step_inner(&mut self, n: usize)->Status<'a> {
+        for _ in 0..n {
+            match self.reduce() {
+                Ok(Step::Continue)=>{},
+                Ok(Step::Finished(data))=>return Status::Finished(data),
+                Ok(Step::Suspended(data, cont))=>{
+                    let prompts = mem::take(&mut self.prompts);
+                    let threads = Box::new(mem::take(&mut self.threads));
+                    let choices = mem::take(&mut self.choices);
+                    return Status::Suspended(data, Resumer {cont, prompts, threads, choices});
+                },
+                Err(e)=>return Status::Error(e),
+            }
+        }
+
+        return Status::Running;
+    }
+
+    /// Runs until the program finishes. Programs that yield to the host have to be driven with
+    /// `step` instead.
+    pub fn run(&mut self)->Result<Data<'a>> {
+        self.slice_start = Instant::now();
+        let res = self.run_inner();
+        self.elapsed += self.slice_start.elapsed();
+
+        return res;
+    }
+
+    fn run_inner(&mut self)->Result<Data<'a>> {
+        loop {
+            match self.reduce()? {
+                Step::Continue=>{},
+                Step::Finished(data)=>return Ok(data),
+                Step::Suspended(..)=>bail!("The program yielded to the host, but it is not being stepped"),
+            }
+        }
+    }
+
+    /// Captures the current continuation.
+    fn capture(&self)->Continuation<'a> {
+        Continuation::Normal {
+            env: self.env.clone(),
+            frames: self.frames.clone(),
+        }
+    }
+
+    /// Performs a single reduction. The machine is left halted if the program finishes, suspends, or
+    /// this returns an error. Errors are thrown to the nearest `catch` first, except for going over
+    /// a limit.
+    fn reduce(&mut self)->Result<Step<'a>> {
+        let state = mem::replace(&mut self.state, State::Halted);
+        if let State::Halted = state {
+            bail!("The machine has already halted");
+        }
+        self.check_limits(&state)?;
+        let state = match state {
+            State::Return(data)=>self.preempt(data),
+            state=>state,
+        };
+
+        self.steps += 1;
+        match self.reduce_state(state) {
+            Ok(Step::Finished(data))=>return self.finish_thread(data),
+            Err(e) if !e.is::<LimitExceeded>() && !e.is::<Deadlock>()=>{
+                let exception = Exception::from_error(&e, self.location());
+                return self.throw(exception, Some(e));
+            },
+            res=>return res,
+        }
+    }
+
+    /// Stops the outermost engine that ran out of fuel, if any, and returns the rest of its
+    /// computation to the engine's caller. Engines only stop when a value is returned, so the rest
+    /// can be resumed like a continuation.
+    fn preempt(&mut self, data: Data<'a>)->State<'a> {
+        let Some(depth) = self.prompts.expired(self.steps) else {
+            return State::Return(data);
+        };
+
+        let prompts = self.prompts.take(depth, self.steps);
+        let (env, frames, ..) = self.prompts.pop_prompt().unwrap();
+        let cont = Continuation::Delimited {
+            env: mem::replace(&mut self.env, env),
+            frames: mem::replace(&mut self.frames, frames),
+            prompts,
+            handlers: &[],
+        };
+
+        return State::Return(Data::Engine(Rc::new(Engine::stopped(cont, data))));
+    }
+
+    /// The variables and frames that returning a value to `ret` continues with.
+    fn return_to(&self, ret: Continuation<'a>)->(Rc<Env<'a>>, Frames<'a>) {
+        match ret {
+            Continuation::Normal{env,frames}=>(env, frames),
+            cont=>{
+                let mut frames = Frames::default();
+                frames.push(Frame::Then(cont));
+                (self.env.clone(), frames)
+            },
+        }
+    }
+
+    /// Runs `engine` on top of an engine prompt that returns to `ret`.
+    fn run_engine(&mut self, ret: Continuation<'a>, engine: Engine<'a>, fuel: u64) {
+        let (env, frames) = self.return_to(ret);
+        self.prompts.push_prompt(env, frames, Delimiter::Engine(self.steps.saturating_add(fuel)));
+
+        self.env = Rc::default();
+        self.frames = Frames::default();
+        self.state = State::Apply(engine.cont, engine.args);
+    }
+
+    /// Runs a generator on top of a generator prompt until it emits a value or finishes, then returns
+    /// what `op` asked for to `ret`.
+    fn run_generator(&mut self, ret: Continuation<'a>, generator: Generator<'a>, op: GenOp<'a>)->Result<()> {
+        let state = generator.start();
+        let (env, frames) = self.return_to(ret);
+        match state {
+            GenState::Paused{cont,args}=>{
+                self.prompts.push_prompt(env, frames, Delimiter::Generator(generator, op));
+                self.env = Rc::default();
+                self.frames = Frames::default();
+                self.state = State::Apply(cont, args);
+            },
+            GenState::Emitted{value,cont}=>{
+                self.env = env;
+                self.frames = frames;
+                self.deliver(generator, op, value, cont);
+            },
+            GenState::Done=>{
+                self.env = env;
+                self.frames = frames;
+                self.generator_finished(generator, op);
+            },
+            GenState::Running=>bail!(ScriptError::new(ErrorKind::Runtime, "The generator is already running")),
+        }
+
+        return Ok(());
+    }
+
+    /// Stops the innermost generator, returning `value` to whoever asked for it. The generator
+    /// continues with `ret` when the next value is asked for.
+    fn emit(&mut self, ret: Continuation<'a>, value: Data<'a>)->Result<()> {
+        let Some(depth) = self.prompts.generator() else {
+            bail!(ScriptError::new(ErrorKind::Runtime, "`emit` can only be used inside a generator"));
+        };
+
+        let (env, frames) = self.return_to(ret);
+        let prompts = self.prompts.take(depth, self.steps);
+        let Some((caller_env, caller_frames, Delimiter::Generator(generator, op))) = self.prompts.pop_prompt() else {
+            unreachable!();
+        };
+        let cont = Continuation::Delimited {
+            env,
+            frames,
+            prompts,
+            handlers: &[],
+        };
+
+        self.env = caller_env;
+        self.frames = caller_frames;
+        self.deliver(generator, op, value, cont);
+
+        return Ok(());
+    }
+
+    /// Returns a value the generator emitted to its caller, in the current frames.
+    fn deliver(&mut self, generator: Generator<'a>, op: GenOp<'a>, value: Data<'a>, cont: Continuation<'a>) {
+        match op {
+            GenOp::Next=>{
+                generator.set(GenState::Paused {cont, args: vec![Data::None]});
+                self.state = State::Return(value);
+            },
+            GenOp::Done=>{
+                generator.set(GenState::Emitted {value, cont});
+                self.state = State::Return(Data::Bool(false));
+            },
+            GenOp::ForEach(f)=>{
+                generator.set(GenState::Paused {cont, args: vec![Data::None]});
+                // `f` continues the loop when it returns
+                let ret = self.capture();
+                let each = f.clone();
+                let next = Native::new(move|_, _|{
+                    Ok(ContRet::Generator(ret.clone(), generator.clone(), GenOp::ForEach(each.clone())))
+                });
+                self.state = State::Apply(f, vec![Data::Continuation(Continuation::Native(next)), value]);
+            },
+        }
+    }
+
+    /// Returns the values to the frames saved by the nearest prompt. The variables set by the body of
+    /// a `reset` or `handle` are kept, like they are after a `try`.
+    fn prompt_returned(&mut self, values: Vec<Data<'a>>) {
+        let (env, frames, delimiter) = self.prompts.pop_prompt().unwrap();
+        self.frames = frames;
+        let returned = match values.len() {
+            1=>State::Return(values.into_iter().next().unwrap()),
+            _=>State::Values(values),
+        };
+        match delimiter {
+            Delimiter::Generator(generator, op)=>{
+                self.env = env;
+                self.generator_finished(generator, op);
+            },
+            Delimiter::Engine(_)=>{
+                self.env = env;
+                self.state = returned;
+            },
+            Delimiter::Reset|Delimiter::Handle(_)=>self.state = returned,
+        }
+    }
+
+    /// Ends the computation up to the nearest prompt, when a call returns without applying its
+    /// return continuation. The callee's variables don't reach the frames after the prompt.
+    fn fall_through(&mut self, values: Vec<Data<'a>>) {
+        let env = self.prompts.env().unwrap_or_else(||self.env.clone());
+        self.jump(env, Frames::default(), values);
+    }
+
+    /// Tells whoever asked a generator for a value that it is done.
+    fn generator_finished(&mut self, generator: Generator<'a>, op: GenOp<'a>) {
+        generator.set(GenState::Done);
+        self.state = match op {
+            GenOp::Done=>State::Return(Data::Bool(true)),
+            GenOp::Next|GenOp::ForEach(_)=>State::Return(Data::None),
+        };
+    }
+
+    fn amb_op(&mut self, op: AmbOp<'a>)->Result<()> {
+        match op {
+            AmbOp::Choose(ret,mut values)=>{
+                values.reverse();
+                let Some(first) = values.pop() else {
+                    return self.fail();
+                };
+                if !values.is_empty() {
+                    self.choices.push(Choice::Alternatives {
+                        cont: ret.clone(),
+                        prompts: self.prompts.clone(),
+                        rest: values,
+                    });
+                }
+                self.state = State::Apply(ret, vec![first]);
+            },
+            AmbOp::Fail=>return self.fail(),
+            AmbOp::Solution(value)=>{
+                let collect = self.choices.iter_mut()
+                    .rev()
+                    .find_map(|choice|match choice {
+                        Choice::Collect{results,..}=>Some(results),
+                        _=>None,
+                    });
+                let Some(results) = collect else {
+                    bail!(ScriptError::new(ErrorKind::Runtime, "The `all-solutions` this solution belongs to has already finished"));
+                };
+                results.push(value);
+                return self.fail();
+            },
+            AmbOp::Collect(ret,f)=>{
+                self.choices.push(Choice::Collect {
+                    cont: ret,
+                    prompts: self.prompts.clone(),
+                    results: Vec::new(),
+                });
+                let solution = Native::new(|_, args|{
+                    Ok(ContRet::Amb(AmbOp::Solution(args.into_iter().next().unwrap_or(Data::None))))
+                });
+                self.state = State::Apply(f, vec![Data::Continuation(Continuation::Native(solution))]);
+            },
+        }
+
+        return Ok(());
+    }
+
+    /// Goes back to the most recent choice point: the next value of an `amb`, or the solutions of an
+    /// `all-solutions` that has none left.
+    fn fail(&mut self)->Result<()> {
+        match self.choices.pop() {
+            Some(Choice::Alternatives{cont,prompts,mut rest})=>{
+                let value = rest.pop().expect("choice points always have a value left");
+                if !rest.is_empty() {
+                    self.choices.push(Choice::Alternatives {
+                        cont: cont.clone(),
+                        prompts: prompts.clone(),
+                        rest,
+                    });
+                }
+                self.prompts = prompts;
+                self.state = State::Apply(cont, vec![value]);
+            },
+            Some(Choice::Collect{cont,prompts,results})=>{
+                self.prompts = prompts;
+                self.state = State::Apply(cont, vec![Data::List(Rc::new(results))]);
+            },
+            None=>bail!(ScriptError::new(ErrorKind::Runtime, "No more choices")),
+        }
+
+        return Ok(());
+    }
+
+    /// Ends the current thread and switches to the next one. The program finishes with the result of
+    /// the main thread once every thread has.
+    fn finish_thread(&mut self, data: Data<'a>)->Result<Step<'a>> {
+        if self.threads.current() == ThreadId::MAIN && !self.threads.has_others() {
+            return Ok(Step::Finished(data));
+        }
+
+        self.threads.finish(data);
+        if self.switch_thread()? {
+            return Ok(Step::Continue);
+        }
+
+        return Ok(Step::Finished(self.threads.take_main_result()));
+    }
+
+    /// Runs the next ready thread. Returns `false` if there are none left and the main thread
+    /// finished. Switching doesn't leave or enter any `dynamic-wind`.
+    fn switch_thread(&mut self)->Result<bool> {
+        let Some(parked) = self.threads.next() else {
+            if self.threads.has_others() && !self.threads.main_finished() {
+                bail!(Deadlock);
+            }
+            return Ok(false);
+        };
+
+        self.prompts = parked.prompts;
+        match parked.cont {
+            Continuation::Normal{env,frames}=>{
+                self.env = env;
+                self.frames = frames;
+                self.state = State::Return(parked.args.into_iter().next().unwrap_or(Data::None));
+            },
+            cont=>{
+                self.env = Rc::default();
+                self.frames = Frames::default();
+                self.state = State::Apply(cont, parked.args);
+            },
+        }
+
+        return Ok(true);
+    }
+
+    /// Parks the current thread until a channel wakes it by applying `cont`.
+    fn wait(&mut self, cont: Continuation<'a>)->Parked<'a> {
+        Parked {
+            id: self.threads.block(),
+            cont,
+            args: Vec::new(),
+            prompts: mem::take(&mut self.prompts),
+        }
+    }
+
+    fn thread_op(&mut self, op: ThreadOp<'a>)->Result<Step<'a>> {
+        match op {
+            ThreadOp::Spawn{ret,cont,mut args}=>{
+                args.insert(0, Data::Continuation(Continuation::Return));
+                let id = self.threads.spawn(cont, args);
+                self.state = State::Apply(ret, vec![Data::Thread(id)]);
+            },
+            ThreadOp::Yield(ret)=>{
+                let prompts = mem::take(&mut self.prompts);
+                self.threads.park(ret, vec![Data::None], prompts);
+                self.switch_thread()?;
+            },
+            ThreadOp::Join(ret,id)=>{
+                let prompts = mem::take(&mut self.prompts);
+                match self.threads.join(id, ret.clone(), prompts.clone()) {
+                    Some(res)=>{
+                        self.prompts = prompts;
+                        self.state = State::Apply(ret, vec![res]);
+                    },
+                    None=>{
+                        self.switch_thread()?;
+                    },
+                }
+            },
+            ThreadOp::Current(ret)=>{
+                self.state = State::Apply(ret, vec![Data::Thread(self.threads.current())]);
+            },
+            ThreadOp::Send(ret,ch,value)=>match ch.try_send(value)? {
+                Sent::Done(woken)=>{
+                    woken.into_iter().for_each(|parked|self.threads.wake(parked));
+                    self.state = State::Apply(ret, vec![Data::None]);
+                },
+                Sent::Full(value)=>{
+                    let parked = self.wait(ret);
+                    ch.wait_to_send(Waiter {parked, value, fired: None});
+                    self.switch_thread()?;
+                },
+            },
+            ThreadOp::Recv(ret,ch)=>match ch.try_recv() {
+                Some((value, woken))=>{
+                    woken.into_iter().for_each(|parked|self.threads.wake(parked));
+                    self.state = State::Apply(ret, vec![value]);
+                },
+                None=>{
+                    let parked = self.wait(ret);
+                    ch.wait_to_recv(Waiter {parked, value: Data::None, fired: None});
+                    self.switch_thread()?;
+                },
+            },
+            ThreadOp::Close(ret,ch)=>{
+                ch.close()?.into_iter().for_each(|parked|self.threads.wake(parked));
+                self.state = State::Apply(ret, vec![Data::None]);
+            },
+        }
+
+        return Ok(Step::Continue);
+    }
+
+    fn reduce_state(&mut self, state: State<'a>)->Result<Step<'a>> {
+        match state {
+            State::Eval(expr)=>self.eval(expr)?,
+            State::Return(data)=>if let Some(frame) = self.frames.pop() {
+                if let Frame::Halt = frame {
+                    return Ok(self.halt(data));
+                }
+                self.ret(frame, data)?;
+            } else if self.prompts.is_empty() {
+                return Ok(Step::Finished(data));
+            } else {
+                self.prompt_returned(vec![data]);
+            },
+            State::Values(values)=>self.ret_values(values)?,
+            State::Apply(cont, args)=>return self.apply(cont, args),
+            State::Halted=>unreachable!(),
+        }
+
+        return Ok(Step::Continue);
+    }
+
+    /// Where the current call or variable is in the source.
+    fn location(&self)->Option<Location> {
+        let s = anchor(self.site?)?;
+        return self.root.locate(s);
+    }
+
+    /// Unwinds to the nearest `catch` and runs its handler, running the `after` of every
+    /// `dynamic-wind` on the way. If nothing catches the exception, the machine halts with `error`,
+    /// or with `Uncaught` for thrown values and errors that went through an `after`.
+    fn throw(&mut self, exception: Exception<'a>, error: Option<Error>)->Result<Step<'a>> {
+        loop {
+            match self.frames.pop() {
+                Some(Frame::Catch{var,handler,env})=>{
+                    self.env = env;
+                    let env = Rc::make_mut(&mut self.env);
+                    env.push_scope();
+                    env.set(var, Data::Error(Rc::new(exception)));
+                    self.frames.push(Frame::PopScope);
+                    self.eval_block(handler);
+
+                    return Ok(Step::Continue);
+                },
+                Some(Frame::Wind{after,env,..})=>{
+                    self.env = env;
+                    self.frames.push(Frame::Rethrow(Rc::new(exception)));
+                    self.state = State::Eval(after);
+
+                    return Ok(Step::Continue);
+                },
+                Some(_)=>{},
+                None=>match self.prompts.pop_prompt() {
+                    Some((_, frames, delimiter))=>{
+                        if let Delimiter::Generator(generator, _) = delimiter {
+                            generator.set(GenState::Done);
+                        }
+                        self.frames = frames;
+                    },
+                    None=>match error {
+                        Some(e)=>return Err(e),
+                        None=>bail!(exception.uncaught()),
+                    },
+                },
+            }
+        }
+    }
+
+    fn check_limits(&self, state: &State<'a>)->Result<()> {
+        if let Some(max) = self.limits.steps {
+            if self.steps >= max {
+                bail!(LimitExceeded::Steps(max));
+            }
+        }
+        if let Some(max) = self.limits.depth {
+            if self.frames.len() + self.prompts.depth() > max {
+                bail!(LimitExceeded::Depth(max));
+            }
+        }
+
+        if !self.steps.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+
+        if let Some(max) = self.limits.time {
+            if self.time_used() > max {
+                bail!(LimitExceeded::Time(max));
+            }
+        }
+        if let Some(max) = self.limits.data_size {
+            let mut counter = SizeCounter::default();
+            counter.env(&self.env);
+            counter.frames(&self.frames);
+            counter.prompts(&self.prompts);
+            counter.threads(&self.threads);
+            counter.choices(&self.choices);
+            counter.size += self.root.code().map_or(0, CodeArena::size);
+            match state {
+                State::Return(data)=>counter.data(data),
+                State::Values(values)=>values.iter().for_each(|data|counter.data(data)),
+                State::Apply(cont, args)=>{
+                    counter.continuation(cont);
+                    args.iter().for_each(|data|counter.data(data));
+                },
+                _=>{},
+            }
+
+            if counter.size > max {
+                bail!(LimitExceeded::DataSize(max));
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn check_strings(&self, args: &[Data<'a>])->Result<()> {
+        let Some(max) = self.limits.string_length else {return Ok(())};
+        for arg in args {
+            if let Data::String(s) = arg {
+                if s.len() > max {
+                    bail!(LimitExceeded::StringLength(max));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn eval(&mut self, expr: &'a Expr<'a>)->Result<()> {
+        self.state = match expr {
+            Expr::DefCont{name,params,body}=>{
+                let cont = Continuation::Function {params, body, caps: self.env.caps};
+                Rc::make_mut(&mut self.env).set(name, Data::Continuation(cont));
+
+                State::Return(Data::None)
+            },
+            Expr::DefGen{name,params,body}=>{
+                let cont = Continuation::GeneratorFunction {params, body, caps: self.env.caps};
+                Rc::make_mut(&mut self.env).set(name, Data::Continuation(cont));
+
+                State::Return(Data::None)
+            },
+            Expr::DefParam{..}=>{
+                self.site = Some(expr);
+                bail!(ScriptError::new(ErrorKind::Runtime, "`defParam` can only be used at the top level"));
+            },
+            Expr::Parameterize{name,value,body}=>{
+                self.frames.push(Frame::Parameterize {site: expr, name, body});
+                State::Eval(value)
+            },
+            Expr::WithContMark{key,value,body}=>{
+                self.frames.push(Frame::MarkArgs {key: None, value, body});
+                State::Eval(key)
+            },
+            Expr::Begin(exprs)=>{
+                self.eval_block(exprs);
+                return Ok(());
+            },
+            Expr::DynamicWind{before,body,after}=>{
+                self.frames.push(Frame::WindBody {before, body, after});
+                State::Eval(before)
+            },
+            Expr::Handle{body,handlers}=>{
+                let frames = mem::take(&mut self.frames);
+                self.prompts.push(self.env.clone(), frames, handlers);
+                State::Eval(body)
+            },
+            Expr::Perform{effect,args}=>return self.next_perform_arg(expr, effect, Vec::new(), args),
+            Expr::Select{clauses,default}=>return self.next_select_operand(expr, clauses, default.as_deref(), Vec::new()),
+            Expr::Match{value,clauses}=>{
+                self.frames.push(Frame::Match {site: expr, clauses});
+                State::Eval(value)
+            },
+            Expr::Reset(body)=>{
+                let frames = mem::take(&mut self.frames);
+                self.prompts.push(self.env.clone(), frames, &[]);
+                self.eval_block(body);
+                return Ok(());
+            },
+            Expr::Shift{var,body}=>{
+                let cont = Continuation::Delimited {
+                    env: self.env.clone(),
+                    frames: mem::take(&mut self.frames),
+                    prompts: Prompts::default(),
+                    handlers: &[],
+                };
+
+                let env = Rc::make_mut(&mut self.env);
+                env.push_scope();
+                env.set(var, Data::Continuation(cont));
+                self.eval_block(body);
+                return Ok(());
+            },
+            Expr::LetCC{var,body}=>{
+                let cont = self.capture();
+
+                let env = Rc::make_mut(&mut self.env);
+                env.push_scope();
+                env.set(var, Data::Continuation(cont));
+                self.frames.push(Frame::PopScope);
+
+                State::Eval(body)
+            },
+            Expr::Call{to_call,args,..}=>{
+                self.frames.push(Frame::Callee {site: expr, args, tail: false});
+                State::Eval(to_call)
+            },
+            Expr::Apply{lhs,args,..}=>{
+                self.frames.push(Frame::Callee {site: expr, args, tail: true});
+                State::Eval(lhs)
+            },
+            Expr::Try{body,var,handler}=>{
+                self.frames.push(Frame::Catch {
+                    var,
+                    handler,
+                    env: self.env.clone(),
+                });
+                self.eval_block(body);
+                return Ok(());
+            },
+            Expr::IfElse{cond,expr,default}=>{
+                self.frames.push(Frame::If {
+                    expr,
+                    default: default.as_deref(),
+                });
+                State::Eval(cond)
+            },
+            Expr::SetVar(name,data)=>{
+                self.frames.push(Frame::SetVar(name));
+                State::Eval(data)
+            },
+            Expr::Receive{vars,expr}=>{
+                self.frames.push(Frame::Receive(vars));
+                State::Eval(expr)
+            },
+            Expr::GetVar(name)=>{
+                if let Some(data) = self.env.get(name) {
+                    State::Return(data.clone())
+                } else if let Some(default) = self.root.param(name) {
+                    match self.param(name) {
+                        Some(data)=>State::Return(data.clone()),
+                        None=>State::Eval(default),
+                    }
+                } else if let Some(cont) = self.root.get(name) {
+                    if let Some(cap) = self.root.native_capability(name).filter(|cap|!self.env.caps.contains(*cap)) {
+                        self.site = Some(expr);
+                        bail!(ScriptError::new(ErrorKind::Runtime, format!("`{name}` needs the `{cap}` capability, which this program is not granted")));
+                    }
+                    State::Return(Data::Continuation(cont))
+                } else {
+                    self.site = Some(expr);
+                    bail!(ScriptError::new(ErrorKind::Unbound, format!("No variable with the name `{name}`")));
+                }
+            },
+            Expr::SetField{..}|Expr::GetField{..}=>bail!("Fields are not supported yet"),
+            Expr::String(s)=>State::Return(Data::String(s.to_string())),
+            Expr::Keyword(k)=>State::Return(Data::Keyword(k)),
+            Expr::Quote(syntax)=>State::Return(syntax.to_data()?),
+            Expr::Number(n)=>State::Return(Data::Number(*n)),
+            Expr::Bool(b)=>State::Return(Data::Bool(*b)),
+            Expr::None=>State::Return(Data::None),
+        };
+
+        return Ok(());
+    }
+
+    /// The value `name` is bound to by the innermost `parameterize` of it, looking through the
+    /// frames of every enclosing `reset` too.
+    fn param(&self, name: &str)->Option<&Data<'a>> {
+        std::iter::once(&self.frames)
+            .chain(self.prompts.iter().map(|(_, frames)|frames))
+            .flat_map(Frames::iter)
+            .find_map(|frame|match frame {
+                Frame::Param{name:n,value} if *n == name=>Some(value),
+                _=>None,
+            })
+    }
+
+    /// Sets a mark on the current frame. If the frames on top are already marks, the expression is
+    /// in tail position of them, so a mark with the same key is replaced instead of pushing another.
+    fn set_mark(&mut self, key: Data<'a>, value: Data<'a>) {
+        let mut marks = Vec::new();
+        while matches!(self.frames.iter().next(), Some(Frame::Mark{..})) {
+            marks.push(self.frames.pop().unwrap());
+        }
+
+        marks.retain(|mark|!matches!(mark, Frame::Mark{key:k,..} if *k == key));
+        for mark in marks.into_iter().rev() {
+            self.frames.push(mark);
+        }
+        self.frames.push(Frame::Mark {key, value});
+    }
+
+    /// The values of every mark with `key`, innermost first, looking through the frames of every
+    /// enclosing `reset` too.
+    fn marks(&self, key: &Data<'a>)->Vec<Data<'a>> {
+        std::iter::once(&self.frames)
+            .chain(self.prompts.iter().map(|(_, frames)|frames))
+            .flat_map(Frames::iter)
+            .filter_map(|frame|match frame {
+                Frame::Mark{key:k,value} if k == key=>Some(value.clone()),
+                _=>None,
+            })
+            .collect()
+    }
+
+    /// Evaluates the default of the next optional parameter the call didn't give, or runs the body
+    /// once they all have values.
+    fn next_default(&mut self, optional: &'a [(&'a str, Expr<'a>)], body: &'a [Expr<'a>]) {
+        for (i, (name, default)) in optional.iter().enumerate() {
+            if self.env.get(name).is_none() {
+                self.frames.push(Frame::Default {name, rest: &optional[i + 1..], body});
+                self.state = State::Eval(default);
+                return;
+            }
+        }
+
+        self.eval_block(body);
+    }
+
+    fn eval_block(&mut self, exprs: &'a [Expr<'a>]) {
+        match exprs.split_first() {
+            Some((first, rest))=>{
+                if !rest.is_empty() {
+                    self.frames.push(Frame::Block(rest));
+                }
+                self.state = State::Eval(first);
+            },
+            None=>self.state = State::Return(Data::None),
+        }
+    }
+
+    fn ret(&mut self, frame: Frame<'a>, data: Data<'a>)->Result<()> {
+        match frame {
+            Frame::Block(rest)=>self.eval_block(rest),
+            Frame::Callee{site,args,tail}=>self.next_arg(site, data, Vec::new(), args, tail)?,
+            Frame::Args{site,callee,mut done,rest,tail}=>{
+                done.push(data);
+                self.next_arg(site, callee, done, rest, tail)?;
+            },
+            Frame::If{expr,default}=>{
+                if data == Data::Bool(true) {
+                    self.state = State::Eval(expr);
+                } else if let Some(default) = default {
+                    self.state = State::Eval(default);
+                } else {
+                    self.state = State::Return(Data::None);
+                }
+            },
+            Frame::SetVar(name)=>{
+                Rc::make_mut(&mut self.env).set(name, data);
+                self.state = State::Return(Data::None);
+            },
+            Frame::Receive(vars)=>{
+                receive(&mut self.env, vars, vec![data])?;
+                self.state = State::Return(Data::None);
+            },
+            Frame::PopScope=>{
+                Rc::make_mut(&mut self.env).pop_scope();
+                self.state = State::Return(data);
+            },
+            Frame::Catch{..}=>self.state = State::Return(data),
+            Frame::WindBody{before,body,after}=>{
+                self.frames.push(Frame::Wind {
+                    before,
+                    after,
+                    env: self.env.clone(),
+                });
+                self.state = State::Eval(body);
+            },
+            Frame::Wind{after,..}=>{
+                self.frames.push(Frame::Value(data));
+                self.state = State::Eval(after);
+            },
+            Frame::Parameterize{site,name,body}=>{
+                if self.root.param(name).is_none() {
+                    self.site = Some(site);
+                    bail!(ScriptError::new(ErrorKind::Unbound, format!("No parameter with the name `{name}`")));
+                }
+                self.frames.push(Frame::Param {name, value: data});
+                self.eval_block(body);
+            },
+            Frame::Param{..}=>self.state = State::Return(data),
+            Frame::MarkArgs{key:None,value,body}=>{
+                self.frames.push(Frame::MarkArgs {key: Some(data), value, body});
+                self.state = State::Eval(value);
+            },
+            Frame::MarkArgs{key:Some(key),body,..}=>{
+                self.set_mark(key, data);
+                self.eval_block(body);
+            },
+            Frame::Mark{..}=>self.state = State::Return(data),
+            Frame::Default{name,rest,body}=>{
+                Rc::make_mut(&mut self.env).set(name, data);
+                self.next_default(rest, body);
+            },
+            Frame::Body{optional,body}=>self.next_default(optional, body),
+            Frame::Value(value)=>self.state = State::Return(value),
+            Frame::Values(values)=>self.state = State::Values(values),
+            Frame::Env(env)=>{
+                self.env = env;
+                self.state = State::Return(data);
+            },
+            Frame::Rewind{env,frames,values}=>self.jump(env, frames, values),
+            Frame::Rethrow(exception)=>{
+                self.throw(Rc::unwrap_or_clone(exception), None)?;
+            },
+            Frame::Perform{site,effect,mut done,rest}=>{
+                done.push(data);
+                self.next_perform_arg(site, effect, done, rest)?;
+            },
+            Frame::Select{site,clauses,default,mut done}=>{
+                done.push(data);
+                self.next_select_operand(site, clauses, default, done)?;
+            },
+            Frame::Selected(clause)=>self.run_clause(clause, data),
+            Frame::Match{site,clauses}=>self.next_match(site, clauses, data)?,
+            Frame::Guard{site,body,rest,value}=>{
+                if data == Data::Bool(true) {
+                    self.frames.push(Frame::PopScope);
+                    self.eval_block(body);
+                } else {
+                    Rc::make_mut(&mut self.env).pop_scope();
+                    self.next_match(site, rest, value)?;
+                }
+            },
+            Frame::Then(cont)=>self.state = State::Apply(cont, vec![data]),
+            Frame::Barrier=>self.fall_through(vec![data]),
+            Frame::Halt=>unreachable!(),
+        }
+
+        return Ok(());
+    }
+
+    /// Returns several values, or none, to the top frame.
+    fn ret_values(&mut self, values: Vec<Data<'a>>)->Result<()> {
+        match self.frames.pop() {
+            Some(Frame::Receive(vars))=>{
+                receive(&mut self.env, vars, values)?;
+                self.state = State::Return(Data::None);
+            },
+            Some(Frame::PopScope)=>{
+                Rc::make_mut(&mut self.env).pop_scope();
+                self.state = State::Values(values);
+            },
+            Some(Frame::Catch{..}|Frame::Param{..}|Frame::Mark{..})=>self.state = State::Values(values),
+            Some(Frame::Wind{after,..})=>{
+                self.frames.push(Frame::Values(values));
+                self.state = State::Eval(after);
+            },
+            Some(Frame::Env(env))=>{
+                self.env = env;
+                self.state = State::Values(values);
+            },
+            Some(Frame::Barrier)=>self.fall_through(values),
+            None if !self.prompts.is_empty()=>self.prompt_returned(values),
+            frame=>{
+                if let Some(frame) = frame {
+                    self.frames.push(frame);
+                }
+                self.state = State::Return(values.into_iter().next().unwrap_or(Data::None));
+            },
+        }
+
+        return Ok(());
+    }
+
+    /// Evaluates the next argument of a `perform`, or runs the handler if there are none left. The
+    /// handler replaces the whole `handle`, and gets everything up to it as a composable
+    /// continuation that runs under the same handlers again.
+    fn next_perform_arg(&mut self, site: &'a Expr<'a>, effect: &'a str, done: Vec<Data<'a>>, args: &'a [Expr<'a>])->Result<()> {
+        if let Some((first, rest)) = args.split_first() {
+            self.frames.push(Frame::Perform {site, effect, done, rest});
+            self.state = State::Eval(first);
+            return Ok(());
+        }
+
+        self.site = Some(site);
+        let Some(depth) = self.prompts.handlers().position(|handlers|handlers.iter().any(|h|h.effect == effect)) else {
+            bail!(ScriptError::new(ErrorKind::Runtime, format!("No handler for the effect `{effect}`")));
+        };
+
+        let prompts = self.prompts.take(depth, self.steps);
+        let Some((env, frames, Delimiter::Handle(handlers))) = self.prompts.pop_prompt() else {
+            unreachable!();
+        };
+        let handler = handlers.iter().find(|h|h.effect == effect).unwrap();
+
+        let (cont_param, params) = handler.params.split_last().unwrap();
+        if params.len() != done.len() {
+            bail!(ScriptError::new(ErrorKind::Arity, format!("The handler for `{effect}` expects {} args, but got {}", params.len(), done.len())));
+        }
+
+        let cont = Continuation::Delimited {
+            env: mem::replace(&mut self.env, env),
+            frames: mem::replace(&mut self.frames, frames),
+            prompts,
+            handlers,
+        };
+
+        let env = Rc::make_mut(&mut self.env);
+        env.push_scope();
+        for (param, arg) in params.iter().zip(done) {
+            env.set(param, arg);
+        }
+        env.set(cont_param, Data::Continuation(cont));
+        self.frames.push(Frame::PopScope);
+        self.eval_block(&handler.body);
+
+        return Ok(());
+    }
+
+    /// Evaluates the next channel or value of a `select`, or runs the first clause that is ready
+    /// once there are none left. If none are, runs the `else` clause, or waits on every channel
+    /// until one is ready.
+    fn next_select_operand(&mut self, site: &'a Expr<'a>, clauses: &'a [SelectClause<'a>], default: Option<&'a [Expr<'a>]>, done: Vec<Data<'a>>)->Result<()> {
+        if let Some(operand) = clauses.iter().flat_map(SelectClause::operands).nth(done.len()) {
+            self.frames.push(Frame::Select {site, clauses, default, done});
+            self.state = State::Eval(operand);
+            return Ok(());
+        }
+
+        self.site = Some(site);
+        let mut operands = done.into_iter();
+        let mut ops = Vec::new();
+        for clause in clauses {
+            let ch = match operands.next() {
+                Some(Data::Channel(ch))=>ch,
+                d=>bail!(ScriptError::new(ErrorKind::Type, format!("Expected channel, but got {}", d.unwrap_or(Data::None).type_name()))),
+            };
+            let value = match clause {
+                SelectClause::Recv{..}=>Data::None,
+                SelectClause::Send{..}=>operands.next().unwrap_or(Data::None),
+            };
+            ops.push((clause, ch, value));
+        }
+
+        let mut waiting = Vec::new();
+        for (clause, ch, value) in ops {
+            let ready = match clause {
+                SelectClause::Recv{..}=>ch.try_recv(),
+                SelectClause::Send{..}=>match ch.try_send(value)? {
+                    Sent::Done(woken)=>Some((Data::None, woken)),
+                    Sent::Full(value)=>{
+                        waiting.push((clause, ch, value));
+                        continue;
+                    },
+                },
+            };
+            match ready {
+                Some((value, woken))=>{
+                    woken.into_iter().for_each(|parked|self.threads.wake(parked));
+                    self.run_clause(clause, value);
+                    return Ok(());
+                },
+                None=>waiting.push((clause, ch, Data::None)),
+            }
+        }
+
+        if let Some(default) = default {
+            self.eval_block(default);
+            return Ok(());
+        }
+
+        let parked = self.wait(Continuation::Return);
+        let fired = Rc::new(Cell::new(false));
+        for (clause, ch, value) in waiting {
+            let mut frames = self.frames.clone();
+            frames.push(Frame::Selected(clause));
+            let waiter = Waiter {
+                parked: Parked {
+                    cont: Continuation::Normal {env: self.env.clone(), frames},
+                    ..parked.clone()
+                },
+                value,
+                fired: Some(fired.clone()),
+            };
+            match clause {
+                SelectClause::Recv{..}=>ch.wait_to_recv(waiter),
+                SelectClause::Send{..}=>ch.wait_to_send(waiter),
+            }
+        }
+        self.switch_thread()?;
+
+        return Ok(());
+    }
+
+    /// Runs the body of a `select` clause, with the received value in its variable.
+    fn run_clause(&mut self, clause: &'a SelectClause<'a>, data: Data<'a>) {
+        if let SelectClause::Recv{var,..} = clause {
+            let env = Rc::make_mut(&mut self.env);
+            env.push_scope();
+            env.set(var, data);
+            self.frames.push(Frame::PopScope);
+        }
+        self.eval_block(clause.body());
+    }
+
+    /// Runs the first of the clauses whose pattern matches the value, with the variables it binds
+    /// in a new scope. A clause with a guard only runs once the guard is true.
+    fn next_match(&mut self, site: &'a Expr<'a>, clauses: &'a [MatchClause<'a>], value: Data<'a>)->Result<()> {
+        for (i, clause) in clauses.iter().enumerate() {
+            let mut vars = Vec::new();
+            if !match_pattern(&clause.pattern, &value, &mut vars) {
+                continue;
+            }
+
+            let env = Rc::make_mut(&mut self.env);
+            env.push_scope();
+            for (var, data) in vars {
+                env.set(var, data);
+            }
+            match &clause.guard {
+                Some(guard)=>{
+                    self.frames.push(Frame::Guard {
+                        site,
+                        body: &clause.body,
+                        rest: &clauses[i + 1..],
+                        value,
+                    });
+                    self.state = State::Eval(guard);
+                },
+                None=>{
+                    self.frames.push(Frame::PopScope);
+                    self.eval_block(&clause.body);
+                },
+            }
+            return Ok(());
+        }
+
+        self.site = Some(site);
+        bail!(ScriptError::new(ErrorKind::Runtime, format!("No `match` clause matched the {}", value.type_name())));
+    }
+
+    /// Replaces the current frames with `frames` and returns `data` to them. First runs the `after`
+    /// of every `dynamic-wind` being left, innermost first, then the `before` of every one being
+    /// entered, outermost first.
+    fn jump(&mut self, env: Rc<Env<'a>>, frames: Frames<'a>, values: Vec<Data<'a>>) {
+        let same_winds = match (self.frames.winds().next(), frames.winds().next()) {
+            (Some(a), Some(b))=>Rc::ptr_eq(a, b),
+            (None, None)=>true,
+            _=>false,
+        };
+        if same_winds {
+            self.env = env;
+            self.frames = frames;
+            self.state = match values.len() {
+                1=>State::Return(values.into_iter().next().unwrap()),
+                _=>State::Values(values),
+            };
+            return;
+        }
+
+        let target: HashSet<_> = frames.winds().map(Rc::as_ptr).collect();
+        let leaving = self.frames.winds().find(|node|!target.contains(&Rc::as_ptr(node))).cloned();
+        if let Some(node) = leaving {
+            let Frame::Wind{after,env:wind_env,..} = &node.frame else {unreachable!()};
+            self.frames = node.next.clone();
+            self.frames.push(Frame::Rewind {env, frames, values});
+            self.env = wind_env.clone();
+            self.state = State::Eval(after);
+            return;
+        }
+
+        let current: HashSet<_> = self.frames.winds().map(Rc::as_ptr).collect();
+        let node = frames.winds()
+            .take_while(|node|!current.contains(&Rc::as_ptr(node)))
+            .last()
+            .cloned()
+            .expect("the winds differ, so one is being entered");
+        let Frame::Wind{before,env:wind_env,..} = &node.frame else {unreachable!()};
+        self.frames = Frames {head: Some(node.clone())};
+        self.frames.push(Frame::Rewind {env, frames, values});
+        self.env = wind_env.clone();
+        self.state = State::Eval(before);
+    }
+
+    /// Leaves the `dynamic-wind`s of each enclosing `reset` in turn, then stops the machine.
+    fn halt(&mut self, data: Data<'a>)->Step<'a> {
+        match self.prompts.pop() {
+            Some((env, frames))=>{
+                self.frames = frames;
+                self.jump(env, Frames::halt(), vec![data]);
+                return Step::Continue;
+            },
+            None=>return Step::Finished(data),
+        }
+    }
+
+    /// Evaluates the next argument of a call, or performs the call if there are none left.
+    fn next_arg(&mut self, site: &'a Expr<'a>, callee: Data<'a>, mut done: Vec<Data<'a>>, args: &'a [Expr<'a>], tail: bool)->Result<()> {
+        if let Some((first, rest)) = args.split_first() {
+            self.frames.push(Frame::Args {site, callee, done, rest, tail});
+            self.state = State::Eval(first);
+            return Ok(());
+        }
+
+        self.site = Some(site);
+        let Data::Continuation(cont) = callee else {
+            bail!(ScriptError::new(ErrorKind::Type, format!("Expected a continuation to call, but got {}", callee.type_name())));
+        };
+        let keywords = take_keywords(site, &cont, &mut done);
+
+        if tail {
+            self.frames = self.frames.floor();
+        } else if !matches!(cont, Continuation::Delimited{..}) {
+            done.insert(0, Data::Continuation(self.capture()));
+            self.frames.push(Frame::Barrier);
+        }
+        match cont {
+            Continuation::Function{params,body,caps} if !keywords.is_empty()=>{
+                self.call_function(params, body, caps, done, keywords)?;
+            },
+            Continuation::GeneratorFunction{params,body,caps} if !keywords.is_empty()=>{
+                self.start_generator(params, body, caps, done, keywords)?;
+            },
+            cont=>self.state = State::Apply(cont, done),
+        }
+
+        return Ok(());
+    }
+
+    /// Runs code made from data on its own, returning its value to `ret`. The code is checked like a
+    /// loaded program, against the capabilities of the code running now, and is kept with the first
+    /// loaded program.
+    fn eval_code(&mut self, ret: Continuation<'a>, code: Data<'a>)->Result<()> {
+        /// The return continuation of the code. Can't be written as a variable, so the code can't see it.
+        const RET: &str = "#ret";
+
+        let Some(arena) = self.root.code() else {
+            bail!(ScriptError::new(ErrorKind::Runtime, "Code can only be evaluated once a program is loaded to keep it"));
+        };
+        let expr = Parser::parse_syntax(Syntax::from_data(&code, arena)?, arena)?;
+        let params = Params {
+            required: vec![RET],
+            ..Params::default()
+        };
+        // the code is kept for as long as the program, so it counts towards the data size from now on
+        let mut counter = SizeCounter::default();
+        counter.data(&code);
+        let (params, body) = arena.alloc_function(params, Expr::Apply {
+            lhs: Box::new(Expr::GetVar(RET)),
+            args: vec![expr],
+            keywords: Vec::new(),
+        }, counter.size);
+
+        let caps = self.env.caps.intersect(self.root.capabilities());
+        check_links(self.root, body, caps)?;
+
+        return self.call_function(params, body, caps, vec![Data::Continuation(ret)], Vec::new());
+    }
+
+    fn call_function(&mut self, params: &'a Params<'a>, body: &'a [Expr<'a>], caps: Capabilities, args: Vec<Data<'a>>, keywords: Vec<(&'a str, Data<'a>)>)->Result<()> {
+        self.env = Rc::new(Env::new(bind_params(params, args, keywords)?, caps));
+        self.next_default(&params.optional, body);
+
+        return Ok(());
+    }
+
+    /// Returns a generator that runs the body with the arguments bound once a value is asked for.
+    fn start_generator(&mut self, params: &'a Params<'a>, body: &'a [Expr<'a>], caps: Capabilities, mut args: Vec<Data<'a>>, keywords: Vec<(&'a str, Data<'a>)>)->Result<()> {
+        // the body's return continuation finishes the generator, like returning from it
+        let done = Continuation::Normal {
+            env: Rc::default(),
+            frames: Frames::default(),
+        };
+        let Some(Data::Continuation(ret)) = args.first_mut().map(|ret|mem::replace(ret, Data::Continuation(done))) else {
+            bail!(ScriptError::new(ErrorKind::Type, "Expected a return continuation for the generator"));
+        };
+
+        let mut frames = Frames::default();
+        frames.push(Frame::Body {optional: &params.optional, body});
+        let cont = Continuation::Normal {
+            env: Rc::new(Env::new(bind_params(params, args, keywords)?, caps)),
+            frames,
+        };
+        let generator = Generator::new(cont, vec![Data::None]);
+        self.state = State::Apply(ret, vec![Data::Generator(generator)]);
+
+        return Ok(());
+    }
+
+    fn apply(&mut self, cont: Continuation<'a>, args: Vec<Data<'a>>)->Result<Step<'a>> {
+        match cont {
+            Continuation::Native(f)=>match f.call(self.root, args)? {
+                ContRet::Apply(cont, args)=>{
+                    self.check_strings(&args)?;
+                    self.state = State::Apply(cont, args);
+                },
+                ContRet::Suspend(cont, data)=>{
+                    self.check_strings(std::slice::from_ref(&data))?;
+                    return Ok(Step::Suspended(data, cont));
+                },
+                ContRet::Thread(op)=>return self.thread_op(op),
+                ContRet::Engine(ret,engine,fuel)=>self.run_engine(ret, engine, fuel),
+                ContRet::Generator(ret,generator,op)=>self.run_generator(ret, generator, op)?,
+                ContRet::Emit(ret,value)=>self.emit(ret, value)?,
+                ContRet::Marks(ret,key)=>{
+                    let marks = self.marks(&key);
+                    self.state = State::Apply(ret, vec![Data::List(Rc::new(marks))]);
+                },
+                ContRet::Amb(op)=>self.amb_op(op)?,
+                ContRet::Eval(ret,code)=>self.eval_code(ret, code)?,
+                ContRet::Throw(Data::Error(exception))=>{
+                    return self.throw(Rc::unwrap_or_clone(exception), None);
+                },
+                ContRet::Throw(data)=>{
+                    let exception = Exception::thrown(data, self.location());
+                    return self.throw(exception, None);
+                },
+            },
+            Continuation::Return=>{
+                let data = args.into_iter().next().unwrap_or(Data::None);
+                self.jump(self.env.clone(), Frames::halt(), vec![data]);
+            },
+            Continuation::Normal{env,frames}=>self.jump(env, frames, args),
+            Continuation::Delimited{env,frames,prompts,handlers}=>{
+                let data = args.into_iter().next().unwrap_or(Data::None);
+                let caller_env = mem::replace(&mut self.env, env);
+                let mut caller = mem::replace(&mut self.frames, frames);
+                caller.push(Frame::Env(caller_env.clone()));
+                self.prompts.push(caller_env, caller, handlers);
+                self.prompts.extend(prompts, self.steps);
+
+                self.state = State::Return(data);
+            },
+            Continuation::Function{params,body,caps}=>self.call_function(params, body, caps, args, Vec::new())?,
+            Continuation::GeneratorFunction{params,body,caps}=>self.start_generator(params, body, caps, args, Vec::new())?,
+        }
+
+        return Ok(Step::Continue);
+    }
+}
+
+
+/// Sets each of a `receive`'s vars to one of the values.
+fn receive<'a>(env: &mut Rc<Env<'a>>, vars: &'a [&'a str], values: Vec<Data<'a>>)->Result<()> {
+    if vars.len() != values.len() {
+        bail!(ScriptError::new(ErrorKind::Arity, format!("`receive` expects {} values, but got {}", vars.len(), values.len())));
+    }
+
+    let env = Rc::make_mut(env);
+    for (var, value) in vars.iter().zip(values) {
+        env.set(var, value);
+    }
+
+    return Ok(());
+}
+
+
+/// Whether `data` has the shape of the pattern, adding the variables it binds to `vars`.
+fn match_pattern<'a>(pattern: &'a Pattern<'a>, data: &Data<'a>, vars: &mut Vec<(&'a str, Data<'a>)>)->bool {
+    match (pattern, data) {
+        (Pattern::Wildcard,_)=>true,
+        (Pattern::Bind(name),_)=>{
+            vars.push((name, data.clone()));
+            true
+        },
+        (Pattern::Literal(lit),_)=>literal_matches(lit, data),
+        (Pattern::AnyOf(lits),_)=>lits.iter().any(|lit|literal_matches(lit, data)),
+        (Pattern::List{items,rest},Data::List(list))=>match_list(items, rest.as_deref(), list, vars),
+        (Pattern::List{..},_)=>false,
+    }
+}
+
+/// Matches the items of a list pattern against a part of a list. Nested rest patterns look at the
+/// same list, so it's only copied for a rest pattern that needs the rest as a value.
+fn match_list<'a>(items: &'a [Pattern<'a>], rest: Option<&'a Pattern<'a>>, list: &[Data<'a>], vars: &mut Vec<(&'a str, Data<'a>)>)->bool {
+    if list.len() < items.len() || (rest.is_none() && list.len() != items.len()) {
+        return false;
+    }
+    if !items.iter().zip(list).all(|(item, data)|match_pattern(item, data, vars)) {
+        return false;
+    }
+
+    let list = &list[items.len()..];
+    match rest {
+        None|Some(Pattern::Wildcard)=>true,
+        Some(Pattern::List{items,rest})=>match_list(items, rest.as_deref(), list, vars),
+        Some(rest)=>match_pattern(rest, &Data::List(Rc::new(list.to_vec())), vars),
+    }
+}
+
+fn literal_matches<'a>(lit: &Expr<'a>, data: &Data<'a>)->bool {
+    match (lit, data) {
+        (Expr::Number(a),Data::Number(b))=>a == b,
+        (Expr::String(a),Data::String(b))=>a == b,
+        (Expr::Keyword(a),Data::Keyword(b))=>a == b,
+        (Expr::Quote(syntax),_)=>syntax.to_data().is_ok_and(|quoted|quoted == *data),
+        (Expr::Bool(a),Data::Bool(b))=>a == b,
+        (Expr::None,Data::None)=>true,
+        _=>false,
+    }
+}
+
+
+/// Takes the arguments a call passed by name out of the others. Only functions take arguments by
+/// name, so anything else gets each keyword before its argument, as it was written.
+fn take_keywords<'a>(site: &'a Expr<'a>, cont: &Continuation<'a>, args: &mut Vec<Data<'a>>)->Vec<(&'a str, Data<'a>)> {
+    let (Expr::Call{keywords,..}|Expr::Apply{keywords,..}) = site else {return Vec::new()};
+
+    let mut named = Vec::new();
+    for &(i, name) in keywords.iter().rev() {
+        match cont {
+            Continuation::Function{..}|Continuation::GeneratorFunction{..}=>named.push((name, args.remove(i))),
+            _=>args.insert(i, Data::Keyword(name)),
+        }
+    }
+    named.reverse();
+
+    return named;
+}
+
+/// Matches a call's arguments to the parameters: keyword arguments first, then the others in order
+/// to the parameters that are left. Optional parameters that weren't given are left unbound, so
+/// their defaults can be evaluated.
+fn bind_params<'a>(params: &'a Params<'a>, args: Vec<Data<'a>>, keywords: Vec<(&'a str, Data<'a>)>)->Result<HashMap<&'a str, Data<'a>>> {
+    let given = args.len();
+    let named = keywords.len();
+    let mut vars = HashMap::new();
+    for (key, value) in keywords {
+        if !params.names().any(|name|name == key) || params.rest == Some(key) {
+            bail!(ScriptError::new(ErrorKind::Arity, format!("Unknown keyword `:{key}` for {params}")));
+        }
+        if vars.insert(key, value).is_some() {
+            bail!(ScriptError::new(ErrorKind::Arity, format!("The keyword `:{key}` was given twice")));
+        }
+    }
+
+    let mut positional = args.into_iter();
+    let names = params.required.iter()
+        .copied()
+        .chain(params.optional.iter().map(|(name, _)|*name));
+    for name in names {
+        if vars.contains_key(name) {
+            continue;
+        }
+        let Some(arg) = positional.next() else {break};
+        vars.insert(name, arg);
+    }
+
+    let extra: Vec<_> = positional.collect();
+    let missing = params.required.iter().find(|name|!vars.contains_key(*name));
+    let expected = match (params.optional.len(), params.rest) {
+        (_, Some(_))=>format!("at least {}", params.required.len()),
+        (0, None)=>params.required.len().to_string(),
+        (n, None)=>format!("{} to {}", params.required.len(), params.required.len() + n),
+    };
+    if (missing.is_some() && named == 0) || (!extra.is_empty() && params.rest.is_none()) {
+        bail!(ScriptError::new(ErrorKind::Arity, format!("Expected {expected} args for {params}, but got {given}")));
+    }
+    if let Some(name) = missing {
+        bail!(ScriptError::new(ErrorKind::Arity, format!("The argument `{name}` of {params} was not given")));
+    }
+    if let Some(rest) = params.rest {
+        vars.insert(rest, Data::List(Rc::new(extra)));
+    }
+
+    return Ok(vars);
+}
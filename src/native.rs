@@ -0,0 +1,413 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    fmt::{
+        Debug,
+        Formatter,
+        Result as FmtResult,
+    },
+    rc::Rc,
+};
+use crate::{
+    Capability,
+    Data,
+    Continuation,
+    ContRet,
+    Channel,
+    ErrorKind,
+    Exception,
+    Generator,
+    RootScope,
+    ScriptError,
+    ThreadId,
+    ret_cont_data,
+};
+
+
+pub type NativeFn<'a> = dyn Fn(&RootScope<'a>, Vec<Data<'a>>)->Result<ContRet<'a>> + 'a;
+
+
+/// A native continuation. Compared by identity, so two natives are only equal if they came from the
+/// same registration.
+#[derive(Clone)]
+pub struct Native<'a>(Rc<NativeFn<'a>>);
+impl<'a> Native<'a> {
+    pub fn new<F: Fn(&RootScope<'a>, Vec<Data<'a>>)->Result<ContRet<'a>> + 'a>(f: F)->Self {
+        Native(Rc::new(f))
+    }
+
+    pub fn call(&self, root: &RootScope<'a>, args: Vec<Data<'a>>)->Result<ContRet<'a>> {
+        (self.0)(root, args)
+    }
+}
+impl<'a> Debug for Native<'a> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "Native({:p})", Rc::as_ptr(&self.0))
+    }
+}
+impl<'a> PartialEq for Native<'a> {
+    fn eq(&self, other: &Self)->bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Describes a native generated by `#[continual::native_module]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NativeInfo {
+    pub name: &'static str,
+    pub doc: &'static str,
+    /// `None` if the native takes a `Rest` of arguments.
+    pub arity: Option<usize>,
+    pub params: &'static [NativeParam],
+    pub capability: Capability,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NativeParam {
+    pub name: &'static str,
+    pub ty: &'static str,
+}
+
+/// Collects every remaining argument of a typed native. Only valid as the sole parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rest<T>(pub Vec<T>);
+
+
+/// Converts a `Data` argument into a Rust value for typed natives.
+pub trait FromData<'a>: Sized {
+    fn from_data(data: Data<'a>)->Result<Self>;
+}
+impl<'a> FromData<'a> for Data<'a> {
+    fn from_data(data: Data<'a>)->Result<Self> {Ok(data)}
+}
+impl<'a> FromData<'a> for i64 {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Number(n)=>Ok(n),
+            d=>bail!("Expected number, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for bool {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Bool(b)=>Ok(b),
+            d=>bail!("Expected bool, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for String {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::String(s)=>Ok(s),
+            d=>bail!("Expected string, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for Continuation<'a> {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Continuation(c)=>Ok(c),
+            d=>bail!("Expected continuation, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for Rc<Exception<'a>> {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Error(e)=>Ok(e),
+            d=>bail!("Expected error, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for ThreadId {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Thread(id)=>Ok(id),
+            d=>bail!("Expected thread, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for Channel<'a> {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Channel(ch)=>Ok(ch),
+            d=>bail!("Expected channel, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for Generator<'a> {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::Generator(generator)=>Ok(generator),
+            d=>bail!("Expected generator, but got {}", d.type_name()),
+        }
+    }
+}
+impl<'a> FromData<'a> for Rc<Vec<Data<'a>>> {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::List(l)=>Ok(l),
+            d=>bail!("Expected list, but got {}", d.type_name()),
+        }
+    }
+}
+/// `#n` becomes `None`, anything else is converted as `T`.
+impl<'a, T: FromData<'a>> FromData<'a> for Option<T> {
+    fn from_data(data: Data<'a>)->Result<Self> {
+        match data {
+            Data::None=>Ok(None),
+            d=>T::from_data(d).map(Some),
+        }
+    }
+}
+
+/// Converts a Rust value into `Data` so typed natives can return it.
+pub trait IntoData<'a> {
+    fn into_data(self)->Data<'a>;
+}
+impl<'a> IntoData<'a> for Data<'a> {
+    fn into_data(self)->Data<'a> {self}
+}
+impl<'a> IntoData<'a> for i64 {
+    fn into_data(self)->Data<'a> {Data::Number(self)}
+}
+impl<'a> IntoData<'a> for bool {
+    fn into_data(self)->Data<'a> {Data::Bool(self)}
+}
+impl<'a> IntoData<'a> for String {
+    fn into_data(self)->Data<'a> {Data::String(self)}
+}
+impl<'a> IntoData<'a> for &str {
+    fn into_data(self)->Data<'a> {Data::String(self.to_string())}
+}
+impl<'a> IntoData<'a> for Continuation<'a> {
+    fn into_data(self)->Data<'a> {Data::Continuation(self)}
+}
+impl<'a> IntoData<'a> for Vec<Data<'a>> {
+    fn into_data(self)->Data<'a> {Data::List(Rc::new(self))}
+}
+impl<'a> IntoData<'a> for () {
+    fn into_data(self)->Data<'a> {Data::None}
+}
+impl<'a, T: IntoData<'a>> IntoData<'a> for Option<T> {
+    fn into_data(self)->Data<'a> {
+        match self {
+            Some(t)=>t.into_data(),
+            None=>Data::None,
+        }
+    }
+}
+
+/// The return value of a typed native. Lets natives return either a plain value or a `Result`.
+pub trait IntoRet<'a> {
+    fn into_ret(self)->Result<Data<'a>>;
+}
+impl<'a, T: IntoData<'a>> IntoRet<'a> for T {
+    fn into_ret(self)->Result<Data<'a>> {Ok(self.into_data())}
+}
+impl<'a, T: IntoData<'a>> IntoRet<'a> for Result<T> {
+    fn into_ret(self)->Result<Data<'a>> {self.map(IntoData::into_data)}
+}
+
+/// The return value of a native that takes its return continuation itself, and tells the machine
+/// what to do next.
+pub trait IntoContRet<'a> {
+    fn into_cont_ret(self)->Result<ContRet<'a>>;
+}
+impl<'a> IntoContRet<'a> for ContRet<'a> {
+    fn into_cont_ret(self)->Result<ContRet<'a>> {Ok(self)}
+}
+impl<'a> IntoContRet<'a> for Result<ContRet<'a>> {
+    fn into_cont_ret(self)->Result<ContRet<'a>> {self}
+}
+
+
+/// Turns a plain Rust function into a native. The implicit continuation is stripped from the args,
+/// the arity and types are checked, and the return value is passed to the continuation.
+pub trait IntoNative<'a, Args> {
+    fn into_native(self, name: &'a str)->Native<'a>;
+}
+
+/// Turns a Rust function that takes the return continuation as its first argument and returns a
+/// `ContRet` into a native. The other arguments are checked like a typed native's.
+pub trait IntoContNative<'a, Args> {
+    fn into_cont_native(self, name: &'a str)->Native<'a>;
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident),*)=>{
+        impl<'a, F, $($arg,)* R> IntoNative<'a, ($($arg,)*)> for F
+        where
+            F: Fn($($arg),*)->R + 'a,
+            $($arg: FromData<'a>,)*
+            R: IntoRet<'a>,
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native(self, name: &'a str)->Native<'a> {
+                Native::new(move|_, args|{
+                    const ARITY: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+
+                    let (cont, args) = split_cont(name, args)?;
+                    check_arity(name, ARITY, args.len())?;
+
+                    let mut args = args.into_iter().enumerate();
+                    $(
+                        let (i, arg) = args.next().unwrap();
+                        let $arg = convert(name, i, arg)?;
+                    )*
+
+                    return ret_cont_data(cont, vec![self($($arg),*).into_ret()?]);
+                })
+            }
+        }
+
+        impl<'a, F, $($arg,)* R> IntoContNative<'a, ($($arg,)*)> for F
+        where
+            F: Fn(Continuation<'a>, $($arg),*)->R + 'a,
+            $($arg: FromData<'a>,)*
+            R: IntoContRet<'a>,
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_cont_native(self, name: &'a str)->Native<'a> {
+                Native::new(move|_, args|{
+                    const ARITY: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+
+                    let (ret, args) = split_ret(name, args)?;
+                    check_arity(name, ARITY, args.len())?;
+
+                    let mut args = args.into_iter().enumerate();
+                    $(
+                        let (i, arg) = args.next().unwrap();
+                        let $arg = convert(name, i, arg)?;
+                    )*
+
+                    return self(ret, $($arg),*).into_cont_ret();
+                })
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(A);
+impl_into_native!(A, B);
+impl_into_native!(A, B, C);
+impl_into_native!(A, B, C, D);
+impl_into_native!(A, B, C, D, E);
+impl_into_native!(A, B, C, D, E, G);
+
+/// Natives whose last parameter takes the rest of the args.
+macro_rules! impl_into_native_rest {
+    ($($arg:ident),*)=>{
+        impl<'a, F, $($arg,)* T, R> IntoNative<'a, ($($arg,)* Rest<T>,)> for F
+        where
+            F: Fn($($arg,)* Rest<T>)->R + 'a,
+            $($arg: FromData<'a>,)*
+            T: FromData<'a>,
+            R: IntoRet<'a>,
+        {
+            #[allow(non_snake_case, unused_mut)]
+            fn into_native(self, name: &'a str)->Native<'a> {
+                Native::new(move|_, args|{
+                    const ARITY: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+
+                    let (cont, args) = split_cont(name, args)?;
+                    check_min_arity(name, ARITY, args.len())?;
+
+                    let mut args = args.into_iter().enumerate();
+                    $(
+                        let (i, arg) = args.next().unwrap();
+                        let $arg = convert(name, i, arg)?;
+                    )*
+                    let mut rest = Vec::with_capacity(args.len());
+                    for (i, arg) in args {
+                        rest.push(convert(name, i, arg)?);
+                    }
+
+                    return ret_cont_data(cont, vec![self($($arg,)* Rest(rest)).into_ret()?]);
+                })
+            }
+        }
+
+        impl<'a, F, $($arg,)* T, R> IntoContNative<'a, ($($arg,)* Rest<T>,)> for F
+        where
+            F: Fn(Continuation<'a>, $($arg,)* Rest<T>)->R + 'a,
+            $($arg: FromData<'a>,)*
+            T: FromData<'a>,
+            R: IntoContRet<'a>,
+        {
+            #[allow(non_snake_case, unused_mut)]
+            fn into_cont_native(self, name: &'a str)->Native<'a> {
+                Native::new(move|_, args|{
+                    const ARITY: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+
+                    let (ret, args) = split_ret(name, args)?;
+                    check_min_arity(name, ARITY, args.len())?;
+
+                    let mut args = args.into_iter().enumerate();
+                    $(
+                        let (i, arg) = args.next().unwrap();
+                        let $arg = convert(name, i, arg)?;
+                    )*
+                    let mut rest = Vec::with_capacity(args.len());
+                    for (i, arg) in args {
+                        rest.push(convert(name, i, arg)?);
+                    }
+
+                    return self(ret, $($arg,)* Rest(rest)).into_cont_ret();
+                })
+            }
+        }
+    };
+}
+
+impl_into_native_rest!();
+impl_into_native_rest!(A);
+impl_into_native_rest!(A, B);
+
+
+fn split_cont<'a>(name: &str, args: Vec<Data<'a>>)->Result<(Data<'a>, Vec<Data<'a>>)> {
+    let mut args = args.into_iter();
+    let Some(cont) = args.next() else {
+        bail!(ScriptError::new(ErrorKind::Arity, format!("Expected continuation for first argument of `{name}`, but got no args")));
+    };
+
+    return Ok((cont, args.collect()));
+}
+
+/// Like `split_cont`, but the continuation has to be one.
+fn split_ret<'a>(name: &str, args: Vec<Data<'a>>)->Result<(Continuation<'a>, Vec<Data<'a>>)> {
+    let (cont, args) = split_cont(name, args)?;
+    let Data::Continuation(ret) = cont else {
+        bail!(ScriptError::new(ErrorKind::Type, format!("Expected continuation for first argument of `{name}`, but got {}", cont.type_name())));
+    };
+
+    return Ok((ret, args));
+}
+
+fn check_arity(name: &str, arity: usize, got: usize)->Result<()> {
+    if got != arity {
+        bail!(ScriptError::new(ErrorKind::Arity, format!("`{name}` expects {arity} args, but got {got}")));
+    }
+
+    return Ok(());
+}
+
+fn check_min_arity(name: &str, arity: usize, got: usize)->Result<()> {
+    if got < arity {
+        bail!(ScriptError::new(ErrorKind::Arity, format!("`{name}` expects at least {arity} args, but got {got}")));
+    }
+
+    return Ok(());
+}
+
+/// Converts argument `i` of the native, not counting the continuation.
+fn convert<'a, T: FromData<'a>>(name: &str, i: usize, arg: Data<'a>)->Result<T> {
+    match T::from_data(arg) {
+        Ok(a)=>Ok(a),
+        Err(e)=>bail!(ScriptError::new(ErrorKind::Type, format!("Argument {} of `{name}`: {e}", i + 1))),
+    }
+}
@@ -3,6 +3,13 @@ use anyhow::{
     bail,
 };
 use logos::Logos;
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    rc::Rc,
+};
 use parser_helper::{
     SimpleError,
     Span,
@@ -11,7 +18,13 @@ use parser_helper::{
     Token as TokenTrait,
     new_parser,
 };
-use crate::ast::*;
+use crate::{
+    arena::CodeArena,
+    ast::*,
+    syntax::*,
+    desugar,
+    exhaustive,
+};
 
 pub use StartOrEnd::*;
 
@@ -19,12 +32,18 @@ pub use StartOrEnd::*;
 #[derive(Debug, Logos, Clone, PartialEq)]
 #[logos(skip "[ \t\r\n]")]
 pub enum Token<'a> {
-    #[regex("[a-zA-Z_][a-zA-Z0-9_]*")]
+    #[regex("[a-zA-Z_][a-zA-Z0-9_\\-?!]*|==")]
     Ident(&'a str),
 
-    #[regex("[0-9][0-9_]*")]
+    #[regex("-?[0-9][0-9_]*")]
     Number(&'a str),
 
+    #[regex(":[a-zA-Z_][a-zA-Z0-9_\\-?!]*", |l|{&l.slice()[1..]})]
+    Keyword(&'a str),
+
+    #[token("&rest")]
+    RestParam,
+
     #[regex("#[a-zA-Z]+", |l|{&l.slice()[1..]})]
     HashLit(&'a str),
 
@@ -34,6 +53,18 @@ pub enum Token<'a> {
     #[token("/")]
     Slash,
 
+    #[token("'")]
+    Quote,
+
+    #[token("`")]
+    Quasiquote,
+
+    #[token(",")]
+    Unquote,
+
+    #[token(",@")]
+    UnquoteSplicing,
+
     #[token("(", |_|Start)]
     #[token(")", |_|End)]
     Paren(StartOrEnd),
@@ -55,38 +86,141 @@ pub enum StartOrEnd {
 }
 
 
-new_parser!(pub struct Parser<'a, 2, Token<'a>, LogosTokenStream<'a, Token<'a>>>);
+/// How many macro uses a top level expression can expand before it's assumed the expansion never
+/// stops.
+const MAX_EXPANSIONS: usize = 1000;
+
+/// The names of the forms `parse_expr` parses itself instead of as a call.
+const SPECIAL_FORMS: &[&str] = &[
+    "defCont", "defGen", "defParam", "defMacro",
+    "parameterize", "with-cont-mark", "letcc", "apply", "set", "receive", "begin",
+    "if", "and", "or", "when", "unless", "cond", "case",
+    "try", "dynamic-wind", "finally", "handle", "perform", "reset", "select", "match", "quote", "shift",
+];
+
+/// The arguments of a call, and the index and name of each one passed by name.
+type Args<'a> = (Vec<Expr<'a>>, Vec<(usize, &'a str)>);
+
+
+new_parser!(pub struct Lexer<'a, 2, Token<'a>, LogosTokenStream<'a, Token<'a>>>);
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    /// The tokens of macro expansions, which are read before the rest of the source.
+    expanded: VecDeque<Token<'a>>,
+    macros: HashMap<&'a str, Rc<Macro<'a>>>,
+    /// The number of macro uses expanded in the current top level expression.
+    expansions: usize,
+    /// The number of variables renamed by macros so far, to keep the new names unique.
+    renamed: usize,
+    /// Every macro use and what it expanded to, if they are being kept.
+    trace: Option<Vec<(Syntax<'a>, Syntax<'a>)>>,
+    /// Problems found in the source that don't stop it from being parsed.
+    warnings: Vec<SimpleError<String>>,
+    /// Where the names made by macros are kept.
+    code: &'a CodeArena<'a>,
+}
 // public methods
 impl<'a> Parser<'a> {
-    pub fn new_from_source(source: &'a str)->Parser<'a> {
-        Parser::new(
-            Token::lexer(source),
-            (),
-        )
+    /// A parser for the source, which keeps the names made by macros in `code`.
+    pub fn new(source: &'a str, code: &'a CodeArena<'a>)->Parser<'a> {
+        Parser {
+            lexer: Lexer::new(
+                Token::lexer(source),
+                (),
+            ),
+            expanded: VecDeque::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            renamed: 0,
+            trace: None,
+            warnings: Vec::new(),
+            code,
+        }
     }
 
     pub fn parse(&mut self)->Result<Vec<Expr<'a>>> {
         let mut out = Vec::new();
         while self.peek() != &Token::EOF {
-            out.push(self.parse_expr()?);
+            self.expansions = 0;
+            if self.peek() == &Token::Paren(Start) && self.peek1() == &Token::Ident("defMacro") {
+                self.parse_def_macro()?;
+            } else {
+                out.push(self.parse_expr()?);
+            }
         }
 
         return Ok(out);
     }
 
+    /// Parses the source, and returns every macro use in it along with what it expanded to, in the
+    /// order they were expanded. Uses inside an expansion come after it.
+    pub fn expansions(&mut self)->Result<Vec<(Syntax<'a>, Syntax<'a>)>> {
+        self.trace = Some(Vec::new());
+        self.parse()?;
+
+        return Ok(self.trace.take().unwrap_or_default());
+    }
+
+    /// The warnings found by `parse` so far: a `match` that doesn't cover every value.
+    pub fn warnings(&self)->&[SimpleError<String>] {
+        &self.warnings
+    }
+
+    /// Parses code made from data by `eval`. It has to be a single expression.
+    pub fn parse_syntax(syntax: Syntax<'a>, code: &'a CodeArena<'a>)->Result<Expr<'a>> {
+        let mut tokens = Vec::new();
+        syntax.tokens(&mut tokens);
+
+        let mut parser = Parser::new("", code);
+        parser.expanded.extend(tokens);
+        let expr = parser.parse_expr()?;
+        if parser.peek() != &Token::EOF {
+            bail!("Expected a single expression");
+        }
+
+        return Ok(expr);
+    }
+
     pub fn parse_expr(&mut self)->Result<Expr<'a>> {
         match self.peek() {
             Token::Paren(Start)=>match self.peek1() {
                 Token::Ident("defCont")=>self.parse_def_cont(),
+                Token::Ident("defGen")=>self.parse_def_gen(),
+                Token::Ident("defParam")=>self.parse_def_param(),
+                Token::Ident("defMacro")=>bail!(self.error("Macros can only be defined at the top level")),
+                Token::Ident("parameterize")=>self.parse_parameterize(),
+                Token::Ident("with-cont-mark")=>self.parse_with_cont_mark(),
                 Token::Ident("letcc")=>self.parse_letcc(),
                 Token::Ident("apply")=>self.parse_apply(),
                 Token::Ident("set")=>self.parse_set(),
+                Token::Ident("receive")=>self.parse_receive(),
                 // Token::Ident("setf")=>self.parse_setf(),
                 Token::Ident("begin")=>self.parse_begin(),
                 // Token::Ident("field")=>self.parse_field(),
                 Token::Ident("if")=>self.parse_if(),
+                Token::Ident("and")=>self.parse_and(),
+                Token::Ident("or")=>self.parse_or(),
+                Token::Ident("when")=>self.parse_when(),
+                Token::Ident("unless")=>self.parse_unless(),
+                Token::Ident("cond")=>self.parse_cond(),
+                Token::Ident("case")=>self.parse_case(),
+                Token::Ident("try")=>self.parse_try(),
+                Token::Ident("dynamic-wind")=>self.parse_dynamic_wind(),
+                Token::Ident("finally")=>self.parse_finally(),
+                Token::Ident("handle")=>self.parse_handle(),
+                Token::Ident("perform")=>self.parse_perform(),
+                Token::Ident("reset")=>self.parse_reset(),
+                Token::Ident("select")=>self.parse_select(),
+                Token::Ident("match")=>self.parse_match(),
+                Token::Ident("quote")=>self.parse_quote(),
+                Token::Ident("shift")=>self.parse_shift(),
                 _=>self.parse_call(),
             },
+            Token::Quote=>{
+                self.next();
+                self.parse_quoted()
+            },
             _=>self.parse_lit(),
         }
     }
@@ -110,6 +244,99 @@ impl<'a> Parser<'a> {
         return Ok(Expr::IfElse {cond, expr, default});
     }
 
+    fn parse_and(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("and")?;
+
+        return self.parse_end_list().map(desugar::and);
+    }
+
+    fn parse_or(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("or")?;
+
+        return self.parse_end_list().map(desugar::or);
+    }
+
+    fn parse_when(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("when")?;
+
+        let cond = self.parse_expr()?;
+        let body = self.parse_end_list()?;
+
+        return Ok(desugar::when(cond, body));
+    }
+
+    fn parse_unless(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("unless")?;
+
+        let cond = self.parse_expr()?;
+        let body = self.parse_end_list()?;
+
+        return Ok(desugar::unless(cond, body));
+    }
+
+    fn parse_cond(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("cond")?;
+
+        let mut clauses = Vec::new();
+        let mut default = None;
+        while !self.try_paren_end() {
+            if default.is_some() {
+                bail!(self.error("The `else` clause of `cond` has to be the last one"));
+            }
+
+            self.paren_start()?;
+            if self.try_match_token(Token::Ident("else")) {
+                default = Some(self.parse_end_list()?);
+            } else {
+                let test = self.parse_expr()?;
+                let body = self.parse_end_list()?;
+                clauses.push((test, body));
+            }
+        }
+
+        return Ok(desugar::cond(clauses, default));
+    }
+
+    fn parse_case(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("case")?;
+
+        let value = self.parse_expr()?;
+
+        let mut clauses = Vec::new();
+        let mut default = None;
+        while !self.try_paren_end() {
+            if default.is_some() {
+                bail!(self.error("The `else` clause of `case` has to be the last one"));
+            }
+
+            self.paren_start()?;
+            if self.try_match_token(Token::Ident("else")) {
+                default = Some(self.parse_end_list()?);
+                continue;
+            }
+
+            self.paren_start()?;
+            let mut datums = Vec::new();
+            while !self.try_paren_end() {
+                let datum = self.parse_expr()?;
+                if !matches!(datum, Expr::Number(_)|Expr::String(_)|Expr::Keyword(_)|Expr::Quote(_)|Expr::Bool(_)|Expr::None) {
+                    bail!(self.error("The datums of a `case` clause must be constants"));
+                }
+                datums.push(datum);
+            }
+            let body = self.parse_end_list()?;
+            clauses.push((datums, body));
+        }
+
+        return Ok(desugar::case(value, clauses, default));
+    }
+
     fn parse_field(&mut self)->Result<Expr<'a>> {
         self.paren_start()?;
         self.match_ident("field")?;
@@ -140,6 +367,26 @@ impl<'a> Parser<'a> {
         return Ok(Expr::SetVar(lhs, data));
     }
 
+    fn parse_receive(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("receive")?;
+
+        self.match_token(Token::Square(Start), "Expected `[`")?;
+        let mut vars = Vec::new();
+        loop {
+            match self.next() {
+                Token::Ident(n)=>vars.push(n),
+                Token::Square(End)=>break,
+                t=>bail!("Unexpected token: `{t:?}`"),
+            }
+        }
+
+        let expr = self.parse_expr().map(Box::new)?;
+        self.paren_end()?;
+
+        return Ok(Expr::Receive {vars, expr});
+    }
+
     fn parse_setf(&mut self)->Result<Expr<'a>> {
         self.paren_start()?;
         self.match_ident("setf")?;
@@ -158,21 +405,105 @@ impl<'a> Parser<'a> {
         self.paren_start()?;
         self.match_ident("defCont")?;
 
+        let (name, params, body) = self.parse_definition()?;
+
+        return Ok(Expr::DefCont {name, params, body});
+    }
+
+    fn parse_def_gen(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("defGen")?;
+
+        let (name, params, body) = self.parse_definition()?;
+        if params.required.is_empty() {
+            bail!(self.error(format!("The generator `{name}` needs a parameter for its return continuation")));
+        }
+
+        return Ok(Expr::DefGen {name, params, body});
+    }
+
+    fn parse_def_param(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("defParam")?;
+
         let name = self.ident()?;
+        let default = self.parse_expr().map(Box::new)?;
+        if !matches!(*default, Expr::Number(_)|Expr::String(_)|Expr::Bool(_)|Expr::None) {
+            bail!(self.error(format!("The default of the parameter `{name}` must be a constant")));
+        }
+        self.paren_end()?;
+
+        return Ok(Expr::DefParam {name, default});
+    }
+
+    fn parse_parameterize(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("parameterize")?;
 
         self.match_token(Token::Square(Start), "Expected `[`")?;
-        let mut params = Vec::new();
+        let name = self.ident()?;
+        let value = self.parse_expr().map(Box::new)?;
+        self.match_token(Token::Square(End), "Expected `]`")?;
+
+        let body = self.parse_end_list()?;
+
+        return Ok(Expr::Parameterize {name, value, body});
+    }
+
+    fn parse_with_cont_mark(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("with-cont-mark")?;
+
+        let key = self.parse_expr().map(Box::new)?;
+        let value = self.parse_expr().map(Box::new)?;
+
+        let body = self.parse_end_list()?;
+
+        return Ok(Expr::WithContMark {key, value, body});
+    }
+
+    /// The name, parameters and body of a `defCont` or `defGen`.
+    fn parse_definition(&mut self)->Result<(&'a str, Params<'a>, Vec<Expr<'a>>)> {
+        let name = self.ident()?;
+        if SPECIAL_FORMS.contains(&name) {
+            // calls to it would always run the special form instead
+            bail!(self.error(format!("`{name}` is a special form, so it can't be defined")));
+        }
+        let params = self.parse_params()?;
+        let body = self.parse_end_list()?;
+
+        return Ok((name, params, body));
+    }
+
+    /// `[required... (optional default)... &rest rest]`
+    fn parse_params(&mut self)->Result<Params<'a>> {
+        self.match_token(Token::Square(Start), "Expected `[`")?;
+        let mut params = Params::default();
         loop {
             match self.next() {
-                Token::Ident(n)=>params.push(n),
+                Token::Ident(n)=>{
+                    if !params.optional.is_empty() {
+                        bail!(self.error(format!("The required parameter `{n}` must come before the optional ones")));
+                    }
+                    params.required.push(n);
+                },
+                Token::Paren(Start)=>{
+                    let name = self.ident()?;
+                    let default = self.parse_expr()?;
+                    self.paren_end()?;
+                    params.optional.push((name, default));
+                },
+                Token::RestParam=>{
+                    params.rest = Some(self.ident()?);
+                    self.match_token(Token::Square(End), "Expected `]` after the rest parameter")?;
+                    break;
+                },
                 Token::Square(End)=>break,
                 t=>bail!("Unexpected token: `{t:?}`"),
             }
         }
 
-        let body = self.parse_end_list()?;
-
-        return Ok(Expr::DefCont {name, params, body});
+        return Ok(params);
     }
 
     fn parse_apply(&mut self)->Result<Expr<'a>> {
@@ -181,9 +512,9 @@ impl<'a> Parser<'a> {
 
         let lhs = self.parse_expr().map(Box::new)?;
 
-        let args = self.parse_end_list()?;
+        let (args, keywords) = self.parse_args()?;
 
-        return Ok(Expr::Apply{lhs,args});
+        return Ok(Expr::Apply{lhs,args,keywords});
     }
 
     fn parse_letcc(&mut self)->Result<Expr<'a>> {
@@ -198,13 +529,376 @@ impl<'a> Parser<'a> {
         return Ok(Expr::LetCC {var, body});
     }
 
+    fn parse_try(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("try")?;
+
+        let mut body = Vec::new();
+        loop {
+            if let Token::Paren(Start) = self.peek() {
+                if let Token::Ident("catch") = self.peek1() {
+                    break;
+                }
+            }
+            body.push(self.parse_expr()?);
+        }
+
+        self.paren_start()?;
+        self.match_ident("catch")?;
+        self.match_token(Token::Square(Start), "Expected `[`")?;
+        let var = self.ident()?;
+        self.match_token(Token::Square(End), "Expected `]`")?;
+        let handler = self.parse_end_list()?;
+        self.paren_end()?;
+
+        return Ok(Expr::Try {body, var, handler});
+    }
+
+    fn parse_dynamic_wind(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("dynamic-wind")?;
+
+        let before = self.parse_expr().map(Box::new)?;
+        let body = self.parse_expr().map(Box::new)?;
+        let after = self.parse_expr().map(Box::new)?;
+        self.paren_end()?;
+
+        return Ok(Expr::DynamicWind {before, body, after});
+    }
+
+    /// `(finally body after)` is `(dynamic-wind #n body after)`.
+    fn parse_finally(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("finally")?;
+
+        let body = self.parse_expr().map(Box::new)?;
+        let after = self.parse_expr().map(Box::new)?;
+        self.paren_end()?;
+
+        return Ok(Expr::DynamicWind {
+            before: Box::new(Expr::None),
+            body,
+            after,
+        });
+    }
+
+    fn parse_handle(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("handle")?;
+
+        let body = self.parse_expr().map(Box::new)?;
+
+        let mut handlers = Vec::new();
+        while !self.try_paren_end() {
+            self.paren_start()?;
+            let effect = self.ident()?;
+
+            self.match_token(Token::Square(Start), "Expected `[`")?;
+            let mut params = Vec::new();
+            loop {
+                match self.next() {
+                    Token::Ident(n)=>params.push(n),
+                    Token::Square(End)=>break,
+                    t=>bail!("Unexpected token: `{t:?}`"),
+                }
+            }
+            if params.is_empty() {
+                bail!(self.error(format!("The handler for `{effect}` needs a parameter for the continuation")));
+            }
+
+            let body = self.parse_end_list()?;
+            handlers.push(Handler {effect, params, body});
+        }
+
+        return Ok(Expr::Handle {body, handlers});
+    }
+
+    fn parse_perform(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("perform")?;
+
+        let effect = self.ident()?;
+
+        let args = self.parse_end_list()?;
+
+        return Ok(Expr::Perform {effect, args});
+    }
+
+    fn parse_select(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("select")?;
+
+        let mut clauses = Vec::new();
+        let mut default = None;
+        while !self.try_paren_end() {
+            if default.is_some() {
+                bail!(self.error("The `else` clause of `select` has to be the last one"));
+            }
+
+            self.paren_start()?;
+            match self.next() {
+                Token::Ident("recv")=>{
+                    let channel = self.parse_expr()?;
+                    self.match_token(Token::Square(Start), "Expected `[`")?;
+                    let var = self.ident()?;
+                    self.match_token(Token::Square(End), "Expected `]`")?;
+                    let body = self.parse_end_list()?;
+                    clauses.push(SelectClause::Recv {channel, var, body});
+                },
+                Token::Ident("send")=>{
+                    let channel = self.parse_expr()?;
+                    let value = self.parse_expr()?;
+                    let body = self.parse_end_list()?;
+                    clauses.push(SelectClause::Send {channel, value, body});
+                },
+                Token::Ident("else")=>default = Some(self.parse_end_list()?),
+                t=>bail!(self.error(format!("Expected `recv`, `send` or `else`, but got `{t:?}`"))),
+            }
+        }
+
+        return Ok(Expr::Select {clauses, default});
+    }
+
+    fn parse_match(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("match")?;
+
+        let value = self.parse_expr().map(Box::new)?;
+
+        let mut clauses = Vec::new();
+        while !self.try_paren_end() {
+            self.paren_start()?;
+            let pattern = self.parse_pattern()?;
+            let guard = match self.try_match_token(Token::Keyword("when")) {
+                true=>Some(self.parse_expr()?),
+                false=>None,
+            };
+            let body = self.parse_end_list()?;
+            clauses.push(MatchClause {pattern, guard, body});
+        }
+
+        if let Some(value) = exhaustive::missing(&clauses) {
+            let warning = match value.as_str() {
+                "_"=>self.error("`match` doesn't cover every value. Add a `_` clause"),
+                _=>self.error(format!("`match` doesn't cover every value, like `{value}`")),
+            };
+            self.warnings.push(warning);
+        }
+
+        return Ok(Expr::Match {value, clauses});
+    }
+
+    fn parse_pattern(&mut self)->Result<Pattern<'a>> {
+        match self.peek() {
+            Token::Ident("_")=>{
+                self.next();
+                Ok(Pattern::Wildcard)
+            },
+            Token::Ident(name)=>{
+                let name = *name;
+                self.next();
+                Ok(Pattern::Bind(name))
+            },
+            Token::Square(Start)=>{
+                self.next();
+                let mut items = Vec::new();
+                let mut rest = None;
+                loop {
+                    match self.peek() {
+                        Token::Square(End)=>{
+                            self.next();
+                            break;
+                        },
+                        Token::RestParam=>{
+                            self.next();
+                            rest = Some(self.parse_pattern().map(Box::new)?);
+                            self.match_token(Token::Square(End), "Expected `]` after the rest pattern")?;
+                            break;
+                        },
+                        _=>items.push(self.parse_pattern()?),
+                    }
+                }
+                Ok(Pattern::List {items, rest})
+            },
+            Token::Quote=>{
+                self.next();
+                self.parse_quoted().map(Pattern::Literal)
+            },
+            Token::Paren(_)=>bail!(self.error("Expected a pattern")),
+            _=>self.parse_lit().map(Pattern::Literal),
+        }
+    }
+
+    fn parse_quote(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("quote")?;
+
+        let expr = self.parse_quoted()?;
+        self.paren_end()?;
+
+        return Ok(expr);
+    }
+
+    fn parse_quoted(&mut self)->Result<Expr<'a>> {
+        let syntax = self.read_syntax()?;
+        if let Err(e) = syntax.to_data() {
+            bail!(self.error(e.to_string()));
+        }
+
+        return Ok(Expr::Quote(syntax));
+    }
+
+    fn parse_reset(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("reset")?;
+
+        return self.parse_end_list().map(Expr::Reset);
+    }
+
+    fn parse_shift(&mut self)->Result<Expr<'a>> {
+        self.paren_start()?;
+        self.match_ident("shift")?;
+
+        let var = self.ident()?;
+
+        let body = self.parse_end_list()?;
+
+        return Ok(Expr::Shift {var, body});
+    }
+
     fn parse_call(&mut self)->Result<Expr<'a>> {
+        if self.expand_macros()? {
+            return self.parse_expr();
+        }
+
         self.paren_start()?;
         let to_call = self.parse_expr().map(Box::new)?;
 
-        let args = self.parse_end_list()?;
+        let (args, keywords) = self.parse_args()?;
+
+        return Ok(Expr::Call {to_call, args, keywords});
+    }
+
+    /// The arguments of a call up to the `)`. A keyword followed by another argument passes that
+    /// argument by name.
+    fn parse_args(&mut self)->Result<Args<'a>> {
+        let mut args = Vec::new();
+        let mut keywords = Vec::new();
+        while !self.try_paren_end() {
+            if let &Token::Keyword(name) = self.peek() {
+                if self.peek1() != &Token::Paren(End) {
+                    self.next();
+                    keywords.push((args.len(), name));
+                }
+            }
+            args.push(self.parse_expr()?);
+        }
+
+        return Ok((args, keywords));
+    }
 
-        return Ok(Expr::Call {to_call, args});
+    /// ``(defMacro name [params... &rest rest] `template)``
+    fn parse_def_macro(&mut self)->Result<()> {
+        self.paren_start()?;
+        self.match_ident("defMacro")?;
+
+        let name = self.ident()?;
+
+        self.match_token(Token::Square(Start), "Expected `[`")?;
+        let mut params = Vec::new();
+        let mut rest = None;
+        loop {
+            match self.next() {
+                Token::Ident(n)=>params.push(n),
+                Token::RestParam=>{
+                    rest = Some(self.ident()?);
+                    self.match_token(Token::Square(End), "Expected `]` after the rest parameter")?;
+                    break;
+                },
+                Token::Square(End)=>break,
+                t=>bail!("Unexpected token: `{t:?}`"),
+            }
+        }
+
+        let Syntax::Quasiquote(template) = self.read_syntax()? else {
+            bail!(self.error(format!("The body of the macro `{name}` has to be a quasiquoted template")));
+        };
+        self.paren_end()?;
+
+        self.macros.insert(name, Rc::new(Macro {name, params, rest, template: *template}));
+
+        return Ok(());
+    }
+
+    /// Expands the next expression until it isn't a macro use. Returns whether it was one.
+    fn expand_macros(&mut self)->Result<bool> {
+        let mut expanded = false;
+        while self.peek() == &Token::Paren(Start) {
+            let &Token::Ident(name) = self.peek1() else {break};
+            let Some(mac) = self.macros.get(name).cloned() else {break};
+            self.expand_macro(&mac)?;
+            expanded = true;
+        }
+
+        return Ok(expanded);
+    }
+
+    /// Replaces the macro use that is next with its expansion.
+    fn expand_macro(&mut self, mac: &Macro<'a>)->Result<()> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            bail!(self.error(format!("Expanding the macro `{}` didn't stop after {MAX_EXPANSIONS} expansions", mac.name)));
+        }
+
+        let Syntax::Paren(mut args) = self.read_syntax()? else {unreachable!()};
+        let name = args.remove(0);
+
+        let renamed = &mut self.renamed;
+        let code = self.code;
+        let expansion = mac.expand(args.clone(), &mut |var|{
+            *renamed += 1;
+            code.alloc_str(&format!("{var}#{renamed}"))
+        });
+        let expansion = match expansion {
+            Ok(expansion)=>expansion,
+            Err(e)=>bail!(self.error(e.to_string())),
+        };
+
+        if let Some(trace) = &mut self.trace {
+            args.insert(0, name);
+            trace.push((Syntax::Paren(args), expansion.clone()));
+        }
+
+        let mut tokens = Vec::new();
+        expansion.tokens(&mut tokens);
+        for token in tokens.into_iter().rev() {
+            self.expanded.push_front(token);
+        }
+
+        return Ok(());
+    }
+
+    /// Reads the next expression as syntax, without parsing it.
+    fn read_syntax(&mut self)->Result<Syntax<'a>> {
+        let read_until = |parser: &mut Self, end: Token<'a>|->Result<Vec<Syntax<'a>>> {
+            let mut items = Vec::new();
+            while !parser.try_match_token(end.clone()) {
+                items.push(parser.read_syntax()?);
+            }
+            Ok(items)
+        };
+
+        match self.next() {
+            Token::Paren(Start)=>read_until(self, Token::Paren(End)).map(Syntax::Paren),
+            Token::Square(Start)=>read_until(self, Token::Square(End)).map(Syntax::Square),
+            Token::Quote=>Ok(Syntax::Quote(Box::new(self.read_syntax()?))),
+            Token::Quasiquote=>Ok(Syntax::Quasiquote(Box::new(self.read_syntax()?))),
+            Token::Unquote=>Ok(Syntax::Unquote(Box::new(self.read_syntax()?))),
+            Token::UnquoteSplicing=>Ok(Syntax::UnquoteSplicing(Box::new(self.read_syntax()?))),
+            Token::EOF=>bail!(self.error("Unexpected end of the source")),
+            t@(Token::Paren(End)|Token::Square(End))=>bail!(self.error(format!("Unexpected token: `{t:?}`"))),
+            t=>Ok(Syntax::Token(t)),
+        }
     }
 
     fn parse_end_list(&mut self)->Result<Vec<Expr<'a>>> {
@@ -229,27 +923,9 @@ impl<'a> Parser<'a> {
                 let len = s.len();
                 Ok(Expr::String(&s[1..len - 1]))
             },
-            Token::Number(n)=>{
-                let mut out = 0i64;
-                let mut mul = 1i64;
-                // iterate through the numbers in reverse so we go from lowest->highest
-                for c in n.chars().rev() {
-                    match c {
-                        '0'..='9'=>{
-                            let c_num = ((c as u8) - b'0') as i64;
-                            let Some(res) = mul.checked_mul(c_num) else {
-                                bail!("Integer overflow");
-                            };
-                            out += res;
-                            mul = mul.saturating_mul(10);
-                        },
-                        _=>{},
-                    }
-                }
-
-                Ok(Expr::Number(out))
-            },
+            Token::Number(n)=>parse_number(n).map(Expr::Number),
             Token::Ident(name)=>Ok(Expr::GetVar(name)),
+            Token::Keyword(name)=>Ok(Expr::Keyword(name)),
             t=>bail!("Unexpected token: {t:?}"),
         }
     }
@@ -259,14 +935,16 @@ impl<'a> Parser<'a> {
 impl<'a> Parser<'a> {
     #[inline]
     fn match_token<M: Into<String>>(&mut self, tok: Token<'a>, msg: M)->Result<()> {
-        self.0.match_token(tok, msg)?;
+        if !self.try_match_token(tok) {
+            bail!(self.error(msg));
+        }
         return Ok(())
     }
 
     #[inline]
     fn try_match_token(&mut self, tok: Token<'a>)->bool {
         if self.peek() == &tok {
-            self.take_token();
+            self.next();
             return true;
         }
 
@@ -275,37 +953,49 @@ impl<'a> Parser<'a> {
 
     #[inline]
     fn peek(&mut self)->&Token<'a> {
-        self.lookahead(0)
+        if !self.expanded.is_empty() {
+            return &self.expanded[0];
+        }
+        self.lexer.lookahead(0)
     }
 
     fn peek1(&mut self)->&Token<'a> {
-        self.lookahead(1)
+        match self.expanded.len() {
+            0=>self.lexer.lookahead(1),
+            1=>self.lexer.lookahead(0),
+            _=>&self.expanded[1],
+        }
     }
     
+    /// The span of the next token in the source. Tokens from macro expansions don't have one of
+    /// their own, so they get the span of the source after the macro use.
     #[inline]
     fn peek_span(&mut self)->Span {
-        self.lookahead_span(0)
+        self.lexer.lookahead_span(0)
     }
 
     #[inline]
     fn next(&mut self)->Token<'a> {
-        self.take_token()
+        match self.expanded.pop_front() {
+            Some(token)=>token,
+            None=>self.lexer.take_token(),
+        }
     }
 
     #[inline]
     fn error(&mut self, msg: impl Into<String>)->SimpleError<String> {
-        self.0.error(msg)
+        self.lexer.0.error(msg)
     }
 
     fn ident(&mut self)->Result<&'a str> {
-        match self.take_token() {
+        match self.next() {
             Token::Ident(s)=>Ok(s),
             _=>bail!(self.error("Expected identifier")),
         }
     }
 
     fn match_ident(&mut self, to_match: &str)->Result<()> {
-        match self.take_token() {
+        match self.next() {
             Token::Ident(s)=>if s != to_match {
                 bail!(self.error(format!("Expected identifier `{}`, but got `{}`", to_match, s)));
             } else {
@@ -316,14 +1006,14 @@ impl<'a> Parser<'a> {
     }
 
     fn paren_start(&mut self)->Result<()> {
-        match self.take_token() {
+        match self.next() {
             Token::Paren(Start)=>Ok(()),
             _=>bail!(self.error("Expected `(`")),
         }
     }
 
     fn paren_end(&mut self)->Result<()> {
-        match self.take_token() {
+        match self.next() {
             Token::Paren(End)=>Ok(()),
             _=>bail!(self.error("Expected `)`")),
         }
@@ -332,10 +1022,39 @@ impl<'a> Parser<'a> {
     fn try_paren_end(&mut self)->bool {
         match self.peek() {
             Token::Paren(End)=>{
-                self.take_token();
+                self.next();
                 true
             },
             _=>false,
         }
     }
 }
+
+
+/// The value of a number token, which can start with a `-`.
+pub(crate) fn parse_number(n: &str)->Result<i64> {
+    let (negative, digits) = match n.strip_prefix('-') {
+        Some(digits)=>(true, digits),
+        None=>(false, n),
+    };
+
+    // build the negative of the number, since `i64::MIN` has no positive counterpart
+    let mut out = 0i64;
+    for c in digits.chars() {
+        if let '0'..='9' = c {
+            let c_num = ((c as u8) - b'0') as i64;
+            let Some(res) = out.checked_mul(10).and_then(|out|out.checked_sub(c_num)) else {
+                bail!("Integer overflow");
+            };
+            out = res;
+        }
+    }
+    if negative {
+        return Ok(out);
+    }
+
+    let Some(out) = out.checked_neg() else {
+        bail!("Integer overflow");
+    };
+    return Ok(out);
+}
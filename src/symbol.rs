@@ -0,0 +1,83 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt::{
+        Debug,
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    ops::Deref,
+    rc::Rc,
+};
+
+
+thread_local! {
+    /// The name of every symbol that exists, so symbols with the same name share it.
+    static SYMBOLS: RefCell<HashSet<Rc<str>>> = RefCell::default();
+}
+
+
+/// An interned name, as in quoted code. Symbols with the same name share it, so they are compared
+/// by pointer. A name is dropped from the table with its last symbol.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+impl Symbol {
+    pub fn new(name: &str)->Self {
+        SYMBOLS.with_borrow_mut(|symbols|{
+            if let Some(name) = symbols.get(name) {
+                return Symbol(name.clone());
+            }
+
+            let name: Rc<str> = name.into();
+            symbols.insert(name.clone());
+            return Symbol(name);
+        })
+    }
+
+    pub fn name(&self)->&str {
+        &self.0
+    }
+}
+impl Drop for Symbol {
+    fn drop(&mut self) {
+        // only the table and this symbol have the name
+        if Rc::strong_count(&self.0) == 2 {
+            // the table is already gone if the thread is exiting
+            let _ = SYMBOLS.try_with(|symbols|symbols.borrow_mut().remove(&self.0));
+        }
+    }
+}
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self)->bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for Symbol {}
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self)->&str {
+        &self.0
+    }
+}
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "{:?}", &*self.0)
+    }
+}
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        write!(f, "{}", &*self.0)
+    }
+}
+impl From<&str> for Symbol {
+    fn from(name: &str)->Self {
+        Symbol::new(name)
+    }
+}
+impl From<String> for Symbol {
+    fn from(name: String)->Self {
+        Symbol::new(&name)
+    }
+}
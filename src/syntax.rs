@@ -0,0 +1,328 @@
+use anyhow::{
+    Result,
+    bail,
+};
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    rc::Rc,
+};
+use crate::{
+    arena::CodeArena,
+    parser::{
+        StartOrEnd,
+        Token,
+        parse_number,
+    },
+    Data,
+};
+
+
+/// The symbol at the start of a list that stands for `[...]` in quoted code.
+pub const SQUARE: &str = "[]";
+
+
+/// Source code read as trees of tokens, before it is parsed. This is what macros take apart and
+/// build.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Syntax<'a> {
+    Token(Token<'a>),
+    /// `(...)`
+    Paren(Vec<Self>),
+    /// `[...]`
+    Square(Vec<Self>),
+    /// `'syntax`
+    Quote(Box<Self>),
+    /// `` `syntax ``
+    Quasiquote(Box<Self>),
+    /// `,syntax`
+    Unquote(Box<Self>),
+    /// `,@syntax`
+    UnquoteSplicing(Box<Self>),
+}
+impl<'a> Syntax<'a> {
+    /// Adds the tokens the syntax was read from to the end of `out`.
+    pub fn tokens(self, out: &mut Vec<Token<'a>>) {
+        let (start, end, items) = match self {
+            Self::Token(token)=>return out.push(token),
+            Self::Quote(syntax)=>return prefixed(Token::Quote, *syntax, out),
+            Self::Quasiquote(syntax)=>return prefixed(Token::Quasiquote, *syntax, out),
+            Self::Unquote(syntax)=>return prefixed(Token::Unquote, *syntax, out),
+            Self::UnquoteSplicing(syntax)=>return prefixed(Token::UnquoteSplicing, *syntax, out),
+            Self::Paren(items)=>(Token::Paren(StartOrEnd::Start), Token::Paren(StartOrEnd::End), items),
+            Self::Square(items)=>(Token::Square(StartOrEnd::Start), Token::Square(StartOrEnd::End), items),
+        };
+
+        out.push(start);
+        for item in items {
+            item.tokens(out);
+        }
+        out.push(end);
+    }
+
+    /// The code as data: names become symbols, `(...)` becomes a list and `[...]` a list starting
+    /// with the symbol `[]`. `'x` becomes `(quote x)`.
+    pub fn to_data(&self)->Result<Data<'a>> {
+        let list = |head: Option<Data<'a>>, items: &[Self]|->Result<Data<'a>> {
+            let items = head.into_iter()
+                .map(Ok)
+                .chain(items.iter().map(Self::to_data))
+                .collect::<Result<_>>()?;
+            Ok(Data::List(Rc::new(items)))
+        };
+
+        match self {
+            Self::Token(token)=>match token {
+                Token::Ident(name)=>Ok(Data::Symbol((*name).into())),
+                Token::Number(n)=>parse_number(n).map(Data::Number),
+                Token::String(s)=>Ok(Data::String(s[1..s.len() - 1].to_string())),
+                Token::Keyword(k)=>Ok(Data::Keyword(k)),
+                Token::HashLit("t")=>Ok(Data::Bool(true)),
+                Token::HashLit("f")=>Ok(Data::Bool(false)),
+                Token::HashLit("n")=>Ok(Data::None),
+                Token::RestParam=>Ok(Data::Symbol("&rest".into())),
+                Token::Slash=>Ok(Data::Symbol("/".into())),
+                _=>bail!("`{self}` can't be quoted"),
+            },
+            Self::Paren(items)=>list(None, items),
+            Self::Square(items)=>list(Some(Data::Symbol(SQUARE.into())), items),
+            Self::Quote(syntax)=>list(Some(Data::Symbol("quote".into())), std::slice::from_ref(syntax)),
+            Self::Quasiquote(_)|Self::Unquote(_)|Self::UnquoteSplicing(_)=>bail!("`{self}` can't be quoted"),
+        }
+    }
+
+    /// The code that `data` is the quoted form of. The opposite of `to_data`. The names and literals
+    /// in it are kept in `code`.
+    pub(crate) fn from_data(data: &Data<'a>, code: &'a CodeArena<'a>)->Result<Self> {
+        let token = match data {
+            Data::Symbol(name)=>Token::Ident(code.alloc_str(name)),
+            Data::Number(n)=>Token::Number(code.alloc_str(&n.to_string())),
+            Data::String(s)=>{
+                if s.contains('"') {
+                    bail!("A string with a `\"` in it can't be turned into code");
+                }
+                Token::String(code.alloc_str(&format!("\"{s}\"")))
+            },
+            Data::Keyword(k)=>Token::Keyword(k),
+            Data::Bool(true)=>Token::HashLit("t"),
+            Data::Bool(false)=>Token::HashLit("f"),
+            Data::None=>Token::HashLit("n"),
+            Data::List(items)=>{
+                let (square, items) = match items.split_first() {
+                    Some((Data::Symbol(head), rest)) if &**head == SQUARE=>(true, rest),
+                    _=>(false, &items[..]),
+                };
+                let items = items.iter()
+                    .map(|item|Self::from_data(item, code))
+                    .collect::<Result<_>>()?;
+                return Ok(match square {
+                    true=>Self::Square(items),
+                    false=>Self::Paren(items),
+                });
+            },
+            d=>bail!("A {} can't be turned into code", d.type_name()),
+        };
+
+        return Ok(Self::Token(token));
+    }
+
+    fn ident(&self)->Option<&'a str> {
+        match self {
+            Self::Token(Token::Ident(name))=>Some(name),
+            _=>None,
+        }
+    }
+}
+/// Shows the syntax like it would be written, so expansions can be read.
+impl<'a> Display for Syntax<'a> {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        let (open, close, items) = match self {
+            Self::Token(token)=>return match token {
+                Token::Ident(s)|Token::Number(s)|Token::String(s)=>write!(f, "{s}"),
+                Token::Keyword(s)=>write!(f, ":{s}"),
+                Token::HashLit(s)=>write!(f, "#{s}"),
+                Token::RestParam=>write!(f, "&rest"),
+                Token::Slash=>write!(f, "/"),
+                Token::Quote=>write!(f, "'"),
+                Token::Quasiquote=>write!(f, "`"),
+                Token::Unquote=>write!(f, ","),
+                Token::UnquoteSplicing=>write!(f, ",@"),
+                Token::Paren(StartOrEnd::Start)=>write!(f, "("),
+                Token::Paren(StartOrEnd::End)=>write!(f, ")"),
+                Token::Square(StartOrEnd::Start)=>write!(f, "["),
+                Token::Square(StartOrEnd::End)=>write!(f, "]"),
+                Token::EOF=>Ok(()),
+            },
+            Self::Quote(syntax)=>return write!(f, "'{syntax}"),
+            Self::Quasiquote(syntax)=>return write!(f, "`{syntax}"),
+            Self::Unquote(syntax)=>return write!(f, ",{syntax}"),
+            Self::UnquoteSplicing(syntax)=>return write!(f, ",@{syntax}"),
+            Self::Paren(items)=>("(", ")", items),
+            Self::Square(items)=>("[", "]", items),
+        };
+
+        write!(f, "{open}")?;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{item}")?;
+        }
+        write!(f, "{close}")
+    }
+}
+
+fn prefixed<'a>(prefix: Token<'a>, syntax: Syntax<'a>, out: &mut Vec<Token<'a>>) {
+    out.push(prefix);
+    syntax.tokens(out);
+}
+
+
+/// A macro defined with ``(defMacro name [params... &rest rest] `template)``. Using it replaces
+/// the use with the template, with each `,param` replaced by the syntax given for it and each
+/// `,@param` by the items of it.
+#[derive(Debug, PartialEq)]
+pub struct Macro<'a> {
+    pub name: &'a str,
+    pub params: Vec<&'a str>,
+    pub rest: Option<&'a str>,
+    pub template: Syntax<'a>,
+}
+impl<'a> Macro<'a> {
+    /// Fills in the template with the arguments. Variables the template binds itself are renamed
+    /// with `rename`, so they can't capture or shadow the variables of the code using the macro.
+    pub fn expand(&self, args: Vec<Syntax<'a>>, rename: &mut impl FnMut(&'a str)->&'a str)->Result<Syntax<'a>> {
+        if args.len() < self.params.len() || (self.rest.is_none() && args.len() > self.params.len()) {
+            let expected = match self.rest {
+                Some(_)=>format!("at least {}", self.params.len()),
+                None=>self.params.len().to_string(),
+            };
+            bail!("The macro `{}` expects {expected} args, but got {}", self.name, args.len());
+        }
+
+        let mut args = args.into_iter();
+        let mut vars: HashMap<_, _> = self.params.iter()
+            .map(|param|(*param, args.next().unwrap()))
+            .collect();
+        if let Some(rest) = self.rest {
+            vars.insert(rest, Syntax::Paren(args.collect()));
+        }
+
+        let mut bound = HashSet::new();
+        binders(&self.template, &mut bound);
+        let renamed = bound.into_iter()
+            .filter(|name|!vars.contains_key(name))
+            .map(|name|(name, rename(name)))
+            .collect();
+
+        let mut out = Vec::new();
+        fill(&self.template, &vars, &renamed, &mut out)?;
+        if out.len() != 1 {
+            bail!("The macro `{}` has to expand to a single expression", self.name);
+        }
+
+        return Ok(out.remove(0));
+    }
+}
+
+/// Adds the template's syntax to `out`, with its unquotes filled in and its bound variables renamed.
+fn fill<'a>(template: &Syntax<'a>, vars: &HashMap<&'a str, Syntax<'a>>, renamed: &HashMap<&'a str, &'a str>, out: &mut Vec<Syntax<'a>>)->Result<()> {
+    let fill_all = |items: &[Syntax<'a>]|->Result<Vec<Syntax<'a>>> {
+        let mut filled = Vec::new();
+        for item in items {
+            fill(item, vars, renamed, &mut filled)?;
+        }
+        Ok(filled)
+    };
+
+    match template {
+        Syntax::Token(Token::Ident(name))=>match renamed.get(name) {
+            Some(new_name)=>out.push(Syntax::Token(Token::Ident(new_name))),
+            None=>out.push(template.clone()),
+        },
+        Syntax::Token(_)=>out.push(template.clone()),
+        Syntax::Paren(items)=>out.push(Syntax::Paren(fill_all(items)?)),
+        Syntax::Square(items)=>out.push(Syntax::Square(fill_all(items)?)),
+        Syntax::Quote(syntax)=>match fill_all(std::slice::from_ref(syntax))?.as_slice() {
+            [syntax]=>out.push(Syntax::Quote(Box::new(syntax.clone()))),
+            _=>bail!("`{template}` has to quote a single expression"),
+        },
+        Syntax::Quasiquote(_)=>bail!("Quasiquotes can't be nested in the template of a macro"),
+        Syntax::Unquote(syntax)=>out.push(unquoted(syntax, vars)?.clone()),
+        Syntax::UnquoteSplicing(syntax)=>match unquoted(syntax, vars)? {
+            Syntax::Paren(items)|Syntax::Square(items)=>out.extend(items.iter().cloned()),
+            _=>bail!("`,@{syntax}` has to be given a list to splice"),
+        },
+    }
+
+    return Ok(());
+}
+
+fn unquoted<'a, 'b>(syntax: &Syntax<'a>, vars: &'b HashMap<&'a str, Syntax<'a>>)->Result<&'b Syntax<'a>> {
+    match syntax.ident().and_then(|name|vars.get(name)) {
+        Some(value)=>Ok(value),
+        None=>bail!("Only the parameters of a macro can be unquoted, but got `{syntax}`"),
+    }
+}
+
+/// Adds the variables the syntax binds, in the places the parser would read them as being bound.
+fn binders<'a>(syntax: &Syntax<'a>, bound: &mut HashSet<&'a str>) {
+    let items = match syntax {
+        Syntax::Paren(items)|Syntax::Square(items)=>items,
+        Syntax::Quasiquote(syntax)=>return binders(syntax, bound),
+        _=>return,
+    };
+    for item in items {
+        binders(item, bound);
+    }
+
+    let square_idents = |syntax: &Syntax<'a>|match syntax {
+        Syntax::Square(items)=>items.iter().filter_map(Syntax::ident).collect(),
+        _=>Vec::new(),
+    };
+    let Some(Syntax::Token(Token::Ident(form))) = items.first() else {return};
+    match *form {
+        "set"|"letcc"|"shift"=>bound.extend(items.get(1).and_then(Syntax::ident)),
+        "receive"|"catch"=>bound.extend(items.get(1).into_iter().flat_map(square_idents)),
+        "recv"=>bound.extend(items.get(2).into_iter().flat_map(square_idents)),
+        "defCont"|"defGen"=>if let Some(Syntax::Square(params)) = items.get(2) {
+            for param in params {
+                match param {
+                    Syntax::Paren(optional)=>bound.extend(optional.first().and_then(Syntax::ident)),
+                    _=>bound.extend(param.ident()),
+                }
+            }
+        },
+        "handle"=>for handler in items.iter().skip(2) {
+            if let Syntax::Paren(handler) = handler {
+                bound.extend(handler.get(1).into_iter().flat_map(square_idents));
+            }
+        },
+        "match"=>for clause in items.iter().skip(2) {
+            if let Syntax::Paren(clause) = clause {
+                pattern_vars(clause.first(), bound);
+            }
+        },
+        _=>{},
+    }
+}
+
+fn pattern_vars<'a>(pattern: Option<&Syntax<'a>>, bound: &mut HashSet<&'a str>) {
+    match pattern {
+        Some(Syntax::Token(Token::Ident("_")))=>{},
+        Some(Syntax::Token(Token::Ident(name)))=>{
+            bound.insert(name);
+        },
+        Some(Syntax::Square(items))=>for item in items {
+            pattern_vars(Some(item), bound);
+        },
+        _=>{},
+    }
+}
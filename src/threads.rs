@@ -0,0 +1,191 @@
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    error::Error,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+};
+use crate::{
+    Channel,
+    Continuation,
+    Data,
+    Prompts,
+};
+
+
+/// A handle to a green thread, returned by `spawn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThreadId(usize);
+impl ThreadId {
+    /// The thread the machine started with.
+    pub const MAIN: ThreadId = ThreadId(0);
+}
+
+/// What a native asks the scheduler to do. The continuations are the native's return continuation.
+pub enum ThreadOp<'a> {
+    /// Starts a thread that calls `cont` with `args`, and returns its handle to `ret`.
+    Spawn {
+        ret: Continuation<'a>,
+        cont: Continuation<'a>,
+        args: Vec<Data<'a>>,
+    },
+    /// Lets the other ready threads run before continuing.
+    Yield(Continuation<'a>),
+    /// Waits for a thread to finish and returns its result.
+    Join(Continuation<'a>, ThreadId),
+    /// Returns the handle of the running thread.
+    Current(Continuation<'a>),
+    /// Sends a value to a channel, waiting for room if it is full.
+    Send(Continuation<'a>, Channel<'a>, Data<'a>),
+    /// Receives a value from a channel, waiting for one if it is empty.
+    Recv(Continuation<'a>, Channel<'a>),
+    Close(Continuation<'a>, Channel<'a>),
+}
+
+/// The error returned when the main thread and every other remaining thread are waiting for each
+/// other or for a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadlock;
+impl Display for Deadlock {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        f.write_str("Deadlock: every thread is blocked")
+    }
+}
+impl Error for Deadlock {}
+
+
+/// A thread that isn't running. It continues by applying `cont` to `args`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Parked<'a> {
+    pub id: ThreadId,
+    pub cont: Continuation<'a>,
+    pub args: Vec<Data<'a>>,
+    pub prompts: Prompts<'a>,
+}
+
+/// The run queue of a machine's green threads. Threads only switch when they yield, block or finish.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheduler<'a> {
+    current: ThreadId,
+    next_id: usize,
+    ready: VecDeque<Parked<'a>>,
+    /// The threads waiting for each thread to finish.
+    joining: HashMap<ThreadId, Vec<Parked<'a>>>,
+    /// The number of threads waiting on a channel. The threads themselves are kept by the channels.
+    blocked: usize,
+    results: HashMap<ThreadId, Data<'a>>,
+}
+impl<'a> Default for Scheduler<'a> {
+    fn default()->Self {
+        Scheduler {
+            current: ThreadId::MAIN,
+            next_id: 1,
+            ready: VecDeque::new(),
+            joining: HashMap::new(),
+            blocked: 0,
+            results: HashMap::new(),
+        }
+    }
+}
+impl<'a> Scheduler<'a> {
+    pub fn current(&self)->ThreadId {
+        self.current
+    }
+
+    /// Whether any thread other than the current one hasn't finished.
+    pub fn has_others(&self)->bool {
+        !self.ready.is_empty() || !self.joining.is_empty() || self.blocked > 0
+    }
+
+    /// Whether the main thread finished. Threads still waiting after that are dropped.
+    pub fn main_finished(&self)->bool {
+        self.results.contains_key(&ThreadId::MAIN)
+    }
+
+    pub(crate) fn spawn(&mut self, cont: Continuation<'a>, args: Vec<Data<'a>>)->ThreadId {
+        let id = ThreadId(self.next_id);
+        self.next_id += 1;
+        self.ready.push_back(Parked {
+            id,
+            cont,
+            args,
+            prompts: Prompts::default(),
+        });
+
+        return id;
+    }
+
+    /// Moves the current thread to the back of the run queue.
+    pub(crate) fn park(&mut self, cont: Continuation<'a>, args: Vec<Data<'a>>, prompts: Prompts<'a>) {
+        self.ready.push_back(Parked {
+            id: self.current,
+            cont,
+            args,
+            prompts,
+        });
+    }
+
+    /// Returns the result of `target` if it finished, or blocks the current thread until it does.
+    pub(crate) fn join(&mut self, target: ThreadId, cont: Continuation<'a>, prompts: Prompts<'a>)->Option<Data<'a>> {
+        if let Some(res) = self.results.get(&target) {
+            return Some(res.clone());
+        }
+
+        self.joining.entry(target).or_default().push(Parked {
+            id: self.current,
+            cont,
+            args: Vec::new(),
+            prompts,
+        });
+
+        return None;
+    }
+
+    /// Marks the current thread as waiting on a channel, which wakes it with `wake`.
+    pub(crate) fn block(&mut self)->ThreadId {
+        self.blocked += 1;
+        return self.current;
+    }
+
+    /// Moves a thread woken by a channel to the back of the run queue.
+    pub(crate) fn wake(&mut self, parked: Parked<'a>) {
+        self.blocked -= 1;
+        self.ready.push_back(parked);
+    }
+
+    /// Records the result of the current thread and wakes the threads waiting for it.
+    pub(crate) fn finish(&mut self, data: Data<'a>) {
+        for mut parked in self.joining.remove(&self.current).unwrap_or_default() {
+            parked.args = vec![data.clone()];
+            self.ready.push_back(parked);
+        }
+        self.results.insert(self.current, data);
+    }
+
+    /// Takes the next thread to run.
+    pub(crate) fn next(&mut self)->Option<Parked<'a>> {
+        let parked = self.ready.pop_front()?;
+        self.current = parked.id;
+
+        return Some(parked);
+    }
+
+    /// The result of the main thread, once every thread finished.
+    pub(crate) fn take_main_result(&mut self)->Data<'a> {
+        self.results.remove(&ThreadId::MAIN).unwrap_or(Data::None)
+    }
+
+    /// Every parked thread and stored result, for measuring live data.
+    pub(crate) fn parked(&self)->impl Iterator<Item = &Parked<'a>> {
+        self.ready.iter().chain(self.joining.values().flatten())
+    }
+
+    pub(crate) fn results(&self)->impl Iterator<Item = &Data<'a>> {
+        self.results.values()
+    }
+}
@@ -0,0 +1,109 @@
+use continual::Data;
+use common::{
+    list,
+    nums,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont below [ret i n]
+    (if (eq i n) (fail))
+    (if (amb #t #f) (apply ret i))
+    (apply below ret (add i 1) n))
+
+(defCont safeFrom [ret queens col i row]
+    (if (eq i row) (apply ret #t))
+    (set q (nth queens i))
+    (if (eq q col) (apply ret #f))
+    (if (eq (sub q col) (sub row i)) (apply ret #f))
+    (if (eq (sub col q) (sub row i)) (apply ret #f))
+    (apply safeFrom ret queens col (add i 1) row))
+(defCont place [ret queens n]
+    (if (eq (length queens) n) (apply ret queens))
+    (set col (below 0 n))
+    (if (safeFrom queens col 0 (length queens)) #n (fail))
+    (apply place ret (append queens (list col)) n))
+(defCont queens6 [ret]
+    (apply place ret (list) 6))
+(defCont countQueens6 [ret]
+    (apply ret (length (all-solutions queens6))))
+
+(defCont triple [ret]
+    (set a (below 1 20))
+    (set b (below a 20))
+    (set c (below b 20))
+    (if (eq (add (mul a a) (mul b b)) (mul c c)) #n (fail))
+    (apply ret (list a b c)))
+(defCont triples [ret]
+    (apply ret (length (all-solutions triple))))
+
+(defCont logged [ret]
+    (set x (amb 1 2 3))
+    (log x)
+    (if (eq x 3) #n (fail))
+    (apply ret x))
+
+(defCont pair [ret]
+    (apply ret (list (amb 1 2) (amb 10 20))))
+(defCont pairs [ret]
+    (apply ret (all-solutions pair)))
+
+(defCont inner [ret]
+    (apply ret (amb 1 2)))
+(defCont outer [ret]
+    (set x (amb 10 20))
+    (apply ret (add x (length (all-solutions inner)))))
+(defCont nested [ret]
+    (apply ret (all-solutions outer)))
+
+(defCont nothing [ret]
+    (apply ret (amb)))
+(defCont none [ret]
+    (apply ret (all-solutions nothing)))
+(defCont exhausted [ret]
+    (apply ret (try (amb) (catch [e] (error-message e)))))
+(defCont failWithValue [ret]
+    (apply ret (try (fail 1) (catch [e] (error-kind e)))))
+"#;
+
+
+fn run(name: &str)->(Data<'static>, Vec<i64>) {
+    common::run_logged(SOURCE, name, ())
+}
+
+#[test]
+fn fail_tries_the_next_choice() {
+    assert_eq!(run("logged"), (Data::Number(3), vec![1, 2, 3]));
+}
+
+#[test]
+fn n_queens() {
+    assert_eq!(run("queens6").0, nums(&[1, 3, 5, 0, 2, 4]));
+    assert_eq!(run("countQueens6").0, Data::Number(4));
+}
+
+#[test]
+fn pythagorean_triples() {
+    assert_eq!(run("triple").0, nums(&[3, 4, 5]));
+    assert_eq!(run("triples").0, Data::Number(5));
+}
+
+#[test]
+fn all_solutions_collects_in_order() {
+    let expected = list(vec![nums(&[1, 10]), nums(&[1, 20]), nums(&[2, 10]), nums(&[2, 20])]);
+    assert_eq!(run("pairs").0, expected);
+    assert_eq!(run("nested").0, nums(&[12, 22]));
+}
+
+#[test]
+fn running_out_of_choices() {
+    assert_eq!(run("none").0, nums(&[]));
+    assert_eq!(run("exhausted").0, Data::String("No more choices".into()));
+}
+
+#[test]
+fn fail_takes_no_args() {
+    assert_eq!(run("failWithValue").0, Data::String("arity".into()));
+}
@@ -0,0 +1,153 @@
+use continual::{
+    Continuation,
+    ContRet,
+    Data,
+    ErrorKind,
+    Interpreter,
+    Program,
+    Rest,
+    ScriptError,
+};
+
+
+const FIB: &str = r#"
+(defCont fib [ret count]
+    (if (eq count 0) (apply ret 0))
+    (apply fibInner ret count 0 1))
+(defCont fibInner [ret count a b]
+    (if (eq count 1) (apply ret b))
+
+    (apply fibInner ret (sub count 1) b (add a b)))
+"#;
+
+
+#[test]
+fn call_named_cont() {
+    let program = Program::parse(FIB).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    assert_eq!(interpreter.call("fib", (10,)).unwrap(), Data::Number(55));
+    assert_eq!(interpreter.call("fib", (0,)).unwrap(), Data::Number(0));
+}
+
+#[test]
+fn apply_without_return() {
+    let program = Program::parse("(defCont main [] (set x 1))").unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    assert!(interpreter.apply("main", ()).is_ok());
+    // `call` passes a return continuation, which `main` has no parameter for
+    assert!(interpreter.call("main", ()).is_err());
+}
+
+#[test]
+fn typed_natives() {
+    let program = Program::parse(r#"
+        (defCont main [ret]
+            (apply ret (concat "n=" (addTwo 40 2))))
+    "#).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+    interpreter.register("addTwo", |a: i64, b: i64|a + b);
+    interpreter.register("concat", |parts: Rest<Data>|{
+        let mut out = String::new();
+        for part in parts.0 {
+            match part {
+                Data::String(s)=>out.push_str(&s),
+                Data::Number(n)=>out.push_str(&n.to_string()),
+                _=>{},
+            }
+        }
+        return out;
+    });
+
+    assert_eq!(interpreter.call("main", ()).unwrap(), Data::String("n=42".into()));
+}
+
+#[test]
+fn native_errors() {
+    let program = Program::parse(r#"
+        (defCont arity [ret] (apply ret (addTwo 1)))
+        (defCont types [ret] (apply ret (addTwo 1 "2")))
+        (defCont bail [ret] (apply ret (rem 1 0)))
+    "#).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+    interpreter.register("addTwo", |a: i64, b: i64|a + b);
+
+    let err = interpreter.call("arity", ()).unwrap_err().to_string();
+    assert!(err.contains("expects 2 args, but got 1"), "{err}");
+
+    let err = interpreter.call("types", ()).unwrap_err().to_string();
+    assert!(err.contains("Expected number, but got string"), "{err}");
+
+    let err = interpreter.call("bail", ()).unwrap_err().to_string();
+    assert!(err.contains("Division by zero"), "{err}");
+}
+
+fn twice<'a>(ret: Continuation<'a>, n: i64)->ContRet<'a> {
+    return ContRet::Apply(ret, vec![Data::Number(n); 2]);
+}
+
+#[test]
+fn cont_natives() {
+    let program = Program::parse(r#"
+        (defCont main [ret]
+            (receive [a b] (twice 21))
+            (apply ret (add a b)))
+        (defCont arity [ret] (apply ret (yield-host)))
+        (defCont types [ret] (apply ret (twice "21")))
+    "#).unwrap();
+    let program = Box::leak(Box::new(program));
+    let mut interpreter = Interpreter::new();
+    interpreter.load(program).unwrap();
+    interpreter.register_cont("twice", twice);
+
+    assert_eq!(interpreter.call("main", ()).unwrap(), Data::Number(42));
+
+    let err = interpreter.call("arity", ()).unwrap_err();
+    let err = err.downcast_ref::<ScriptError>().unwrap();
+    assert_eq!((err.kind, err.message.as_str()), (ErrorKind::Arity, "`yield-host` expects 1 args, but got 0"));
+
+    let err = interpreter.call("types", ()).unwrap_err();
+    let err = err.downcast_ref::<ScriptError>().unwrap();
+    assert_eq!((err.kind, err.message.as_str()), (ErrorKind::Type, "Argument 1 of `twice`: Expected number, but got string"));
+}
+
+#[test]
+fn unknown_cont() {
+    let interpreter = Interpreter::new();
+    let err = interpreter.call("missing", ()).unwrap_err().to_string();
+    assert!(err.contains("No continuation named `missing`"), "{err}");
+}
+
+#[test]
+fn empty_has_no_builtins() {
+    let program = Program::parse(FIB).unwrap();
+    let mut interpreter = Interpreter::empty();
+    interpreter.load(&program).unwrap();
+
+    assert!(interpreter.get("add").is_none());
+    assert!(interpreter.call("fib", (3,)).is_err());
+}
+
+#[test]
+fn later_loads_replace_definitions() {
+    let first = Program::parse("(defCont value [ret] (apply ret 1))").unwrap();
+    let second = Program::parse("(defCont value [ret] (apply ret 2))").unwrap();
+    let mut interpreter = Interpreter::new();
+
+    interpreter.load(&first).unwrap();
+    assert_eq!(interpreter.call("value", ()).unwrap(), Data::Number(1));
+
+    interpreter.load(&second).unwrap();
+    assert_eq!(interpreter.call("value", ()).unwrap(), Data::Number(2));
+}
+
+#[test]
+fn parse_errors() {
+    assert!(Program::parse("(defCont main [").is_err());
+    assert!(Program::parse("#q").is_err());
+}
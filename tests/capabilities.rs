@@ -0,0 +1,89 @@
+use continual::{
+    builtins::Builtins,
+    Capabilities,
+    Capability,
+    Data,
+    Interpreter,
+    Program,
+};
+
+
+#[test]
+fn denied_natives_fail_to_link() {
+    let program = Program::parse(r#"(defCont main [ret] (println "hi") (apply ret 1))"#).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_capabilities(Capabilities::pure());
+
+    let err = interpreter.load(&program).unwrap_err().to_string();
+    assert!(err.contains("`println` needs the `stdout` capability"), "{err}");
+    assert!(interpreter.get("println").is_none());
+    assert!(interpreter.get("main").is_none());
+}
+
+#[test]
+fn per_program_capabilities() {
+    let printer = Program::parse(r#"(defCont greet [ret] (println "hi") (apply ret 1))"#).unwrap();
+    let pure = Program::parse(r#"(defCont main [ret] (apply ret (greet)))"#).unwrap();
+    let sneaky = Program::parse(r#"(defCont sneaky [ret] (apply ret (readFile "secret")))"#).unwrap();
+
+    let mut interpreter = Interpreter::new();
+    interpreter.register_with_capability("readFile", Capability::FsRead, |_: String|"contents");
+    interpreter.load_with_capabilities(&printer, Capabilities::pure().with(Capability::Stdout)).unwrap();
+    interpreter.load_with_capabilities(&pure, Capabilities::pure()).unwrap();
+
+    let err = interpreter.load_with_capabilities(&sneaky, Capabilities::pure().with(Capability::Stdout))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("`readFile` needs the `filesystem-read` capability"), "{err}");
+
+    // the pure program can still use continuations it was given by more capable programs
+    assert_eq!(interpreter.call("main", ()).unwrap(), Data::Number(1));
+}
+
+#[test]
+fn bound_names_shadow_natives() {
+    let program = Program::parse(r#"
+        (defCont main [ret println]
+            (set x (println 1))
+            (apply ret x))
+    "#).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load_with_capabilities(&program, Capabilities::pure()).unwrap();
+}
+
+#[test]
+fn natives_are_checked_when_looked_up() {
+    let branch = Program::parse(r#"
+        (defCont main [ret]
+            (if #f (set println 0) #n)
+            (println "hi")
+            (apply ret 1))
+    "#).unwrap();
+    let later = Program::parse(r#"
+        (defCont later [ret]
+            (println "hi")
+            (set println 0)
+            (apply ret 1))
+    "#).unwrap();
+
+    let mut interpreter = Interpreter::new();
+    let err = interpreter.load_with_capabilities(&branch, Capabilities::pure()).unwrap_err().to_string();
+    assert!(err.contains("`println` needs the `stdout` capability"), "{err}");
+
+    interpreter.load_with_capabilities(&later, Capabilities::pure()).unwrap();
+    let err = interpreter.call("later", ()).unwrap_err().to_string();
+    assert!(err.contains("`println` needs the `stdout` capability"), "{err}");
+}
+
+#[test]
+fn builtin_capabilities() {
+    let println = Builtins::NATIVES.iter().find(|n|n.name == "println").unwrap();
+    assert_eq!(println.capability, Capability::Stdout);
+
+    let add = Builtins::NATIVES.iter().find(|n|n.name == "add").unwrap();
+    assert_eq!(add.capability, Capability::Pure);
+
+    let caps = Capabilities::all().without(Capability::Stdout).without(Capability::Pure);
+    assert!(caps.contains(Capability::Pure));
+    assert!(!caps.contains(Capability::Stdout));
+}
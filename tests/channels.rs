@@ -0,0 +1,148 @@
+use continual::{
+    Data,
+    Deadlock,
+    ErrorKind,
+    ScriptError,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont produce [ret ch n]
+    (if (eq n 0) (begin
+        (close ch)
+        (apply ret #n)))
+    (send ch n)
+    (apply produce ret ch (sub n 1)))
+(defCont double [ret in out]
+    (set v (recv in))
+    (if (eq v #n) (begin
+        (close out)
+        (apply ret #n)))
+    (send out (mul v 2))
+    (apply double ret in out))
+(defCont sum [ret ch total]
+    (set v (recv ch))
+    (if (eq v #n) (apply ret total))
+    (apply sum ret ch (add total v)))
+
+(defCont pipeline [ret]
+    (set a (chan 0))
+    (set b (chan 2))
+    (spawn produce a 5)
+    (spawn double a b)
+    (apply ret (sum b 0)))
+
+(defCont sender [ret ch]
+    (log 1)
+    (send ch 10)
+    (log 2)
+    (apply ret #n))
+(defCont rendezvous [ret]
+    (set ch (chan 0))
+    (spawn sender ch)
+    (log 0)
+    (set v (recv ch))
+    (log v)
+    (apply ret v))
+
+(defCont buffered [ret]
+    (set ch (chan 2))
+    (send ch 1)
+    (send ch 2)
+    (close ch)
+    (set total (add (recv ch) (recv ch)))
+    (apply ret (if (eq (recv ch) #n) total 0)))
+
+(defCont sendClosed [ret]
+    (set ch (chan 1))
+    (close ch)
+    (apply ret (try (send ch 1) (catch [e] (error-message e)))))
+
+(defCont pick [ret a b]
+    (apply ret (select
+        (recv a [v] (add v 100))
+        (recv b [v] (add v 200))
+        (else 0))))
+(defCont selectDefault [ret]
+    (apply ret (pick (chan 1) (chan 1))))
+(defCont selectReady [ret]
+    (set a (chan 1))
+    (set b (chan 1))
+    (send b 5)
+    (apply ret (pick a b)))
+
+(defCont delayed [ret ch v]
+    (yield)
+    (send ch v)
+    (apply ret #n))
+(defCont selectWaits [ret]
+    (set a (chan 0))
+    (set b (chan 0))
+    (spawn delayed b 7)
+    (apply ret (select
+        (recv a [v] v)
+        (recv b [v] (mul v 2)))))
+
+(defCont selectSend [ret]
+    (set full (chan 0))
+    (set open (chan 1))
+    (select
+        (send full 1 (log 1))
+        (send open 2 (log 2)))
+    (apply ret (recv open)))
+
+(defCont stuck [ret]
+    (apply ret (recv (chan 0))))
+
+(defCont recvNumber [ret] (apply ret (recv 5)))
+(defCont sendNothing [ret] (apply ret (send (chan 1))))
+"#;
+
+
+fn run(name: &str)->(anyhow::Result<Data<'static>>, Vec<i64>) {
+    common::call_logged(SOURCE, name, ())
+}
+
+#[test]
+fn pipelines() {
+    assert_eq!(run("pipeline").0.unwrap(), Data::Number(30));
+}
+
+#[test]
+fn unbuffered_sends_wait_for_a_receiver() {
+    let (res, log) = run("rendezvous");
+    assert_eq!(res.unwrap(), Data::Number(10));
+    assert_eq!(log, vec![0, 1, 2, 10]);
+}
+
+#[test]
+fn closed_channels() {
+    assert_eq!(run("buffered").0.unwrap(), Data::Number(3));
+    assert_eq!(run("sendClosed").0.unwrap(), Data::String("Sent to a closed channel".into()));
+}
+
+#[test]
+fn select_runs_the_ready_clause() {
+    assert_eq!(run("selectDefault").0.unwrap(), Data::Number(0));
+    assert_eq!(run("selectReady").0.unwrap(), Data::Number(205));
+    assert_eq!(run("selectWaits").0.unwrap(), Data::Number(14));
+
+    let (res, log) = run("selectSend");
+    assert_eq!(res.unwrap(), Data::Number(2));
+    assert_eq!(log, vec![2]);
+}
+
+#[test]
+fn waiting_forever_is_a_deadlock() {
+    assert!(run("stuck").0.unwrap_err().is::<Deadlock>());
+}
+
+#[test]
+fn channel_natives_check_their_args() {
+    for (name, kind) in [("recvNumber", ErrorKind::Type), ("sendNothing", ErrorKind::Arity)] {
+        let err = run(name).0.unwrap_err();
+        assert_eq!(err.downcast_ref::<ScriptError>().unwrap().kind, kind, "{name}");
+    }
+}
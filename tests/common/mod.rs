@@ -0,0 +1,70 @@
+// every test file uses a different part of this
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+use anyhow::Result;
+use continual::{
+    Data,
+    Interpreter,
+    IntoArgs,
+    Program,
+};
+
+
+/// A new interpreter with the builtins and the program loaded.
+pub fn interpreter<'a>(program: &'a Program<'a>)->Interpreter<'a> {
+    let mut interpreter = Interpreter::new();
+    interpreter.load(program).unwrap();
+
+    return interpreter;
+}
+
+/// Like `interpreter`, but parses the source first. The program is leaked, so the data the
+/// interpreter returns can outlive it.
+pub fn load(source: &'static str)->Interpreter<'static> {
+    let program = Box::leak(Box::new(Program::parse(source).unwrap()));
+
+    return interpreter(program);
+}
+
+/// Adds a `log` native to the interpreter, which keeps every number passed to it in the returned
+/// list.
+pub fn log(interpreter: &mut Interpreter<'static>)->Rc<RefCell<Vec<i64>>> {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let log_native = log.clone();
+    interpreter.register("log", move|n: i64|log_native.borrow_mut().push(n));
+
+    return log;
+}
+
+/// Calls `name` from the source, and returns what it returned.
+pub fn run<A: IntoArgs<'static>>(source: &'static str, name: &str, args: A)->Data<'static> {
+    return load(source).call(name, args).unwrap();
+}
+
+/// Calls `name` from the source, and returns the result along with every number passed to `log`.
+pub fn call_logged<A: IntoArgs<'static>>(source: &'static str, name: &str, args: A)->(Result<Data<'static>>, Vec<i64>) {
+    let mut interpreter = load(source);
+    let log = log(&mut interpreter);
+
+    let res = interpreter.call(name, args);
+    return (res, log.take());
+}
+
+/// Like `call_logged`, but the call has to succeed.
+pub fn run_logged<A: IntoArgs<'static>>(source: &'static str, name: &str, args: A)->(Data<'static>, Vec<i64>) {
+    let (res, log) = call_logged(source, name, args);
+
+    return (res.unwrap(), log);
+}
+
+pub fn list(items: Vec<Data<'static>>)->Data<'static> {
+    Data::List(Rc::new(items))
+}
+
+pub fn nums(items: &[i64])->Data<'static> {
+    list(items.iter().copied().map(Data::Number).collect())
+}
@@ -0,0 +1,105 @@
+use continual::{
+    Data,
+    Program,
+};
+use common::list;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont divides [ret x y]
+    (apply ret (and (eq (eq x 0) #f) (eq (rem y x) 0))))
+(defCont either [ret x y]
+    (apply ret (or (eq x 0) (eq (rem y x) 0))))
+
+(defCont asValues [ret]
+    (set all and)
+    (set any or)
+    (apply ret (list (all #t #f) (any #f #t))))
+
+(defCont logged [ret]
+    (set a (and (log 1) (log 2)))
+    (set b (or (eq (log 3) #n) (log 4)))
+    (apply ret (list a b (and) (or))))
+
+(defCont sign [ret n]
+    (apply ret (cond
+        ((eq n 0) "zero")
+        ((eq (rem n 2) 0) (log n) "even")
+        (else "odd"))))
+(defCont noElse [ret]
+    (apply ret (cond ((eq 1 2) 1))))
+
+(defCont whenUnless [ret x]
+    (when (eq x 1) (log 1) (log 2))
+    (unless (eq x 1) (log 3))
+    (apply ret (list (when #f 1) (unless #f 1))))
+
+(defCont kind [ret x]
+    (apply ret (case x
+        ((1 2 3) "small")
+        (("a" :b) "letter")
+        ((#n) "nothing")
+        (else "other"))))
+(defCont caseNoElse [ret]
+    (apply ret (case 5 ((1) "one"))))
+(defCont caseShadowed [ret eq]
+    (apply ret (case 2 ((1 2) "found") (else "other"))))
+"#;
+
+
+fn run(name: &str, args: Vec<Data<'static>>)->(Data<'static>, Vec<i64>) {
+    common::run_logged(SOURCE, name, args)
+}
+
+#[test]
+fn and_or_short_circuit() {
+    assert_eq!(run("divides", vec![Data::Number(0), Data::Number(5)]).0, Data::Bool(false));
+    assert_eq!(run("divides", vec![Data::Number(5), Data::Number(10)]).0, Data::Bool(true));
+    assert_eq!(run("either", vec![Data::Number(0), Data::Number(5)]).0, Data::Bool(true));
+    assert_eq!(run("either", vec![Data::Number(3), Data::Number(5)]).0, Data::Bool(false));
+
+    let (res, log) = run("logged", vec![]);
+    assert_eq!(res, list(vec![Data::Bool(false), Data::Bool(true), Data::Bool(true), Data::Bool(false)]));
+    assert_eq!(log, vec![1, 3]);
+}
+
+#[test]
+fn cond_runs_the_first_true_clause() {
+    assert_eq!(run("sign", vec![Data::Number(0)]), (Data::String("zero".into()), vec![]));
+    assert_eq!(run("sign", vec![Data::Number(4)]), (Data::String("even".into()), vec![4]));
+    assert_eq!(run("sign", vec![Data::Number(5)]), (Data::String("odd".into()), vec![]));
+    assert_eq!(run("noElse", vec![]).0, Data::None);
+}
+
+#[test]
+fn when_and_unless() {
+    assert_eq!(run("whenUnless", vec![Data::Number(1)]), (list(vec![Data::None, Data::Number(1)]), vec![1, 2]));
+    assert_eq!(run("whenUnless", vec![Data::Number(2)]).1, vec![3]);
+}
+
+#[test]
+fn case_compares_to_the_datums() {
+    let kind = |x|run("kind", vec![x]).0;
+    assert_eq!(kind(Data::Number(2)), Data::String("small".into()));
+    assert_eq!(kind(Data::String("a".into())), Data::String("letter".into()));
+    assert_eq!(kind(Data::None), Data::String("nothing".into()));
+    assert_eq!(kind(Data::Number(7)), Data::String("other".into()));
+    assert_eq!(run("caseNoElse", vec![]).0, Data::None);
+    assert_eq!(run("caseShadowed", vec![Data::Number(0)]).0, Data::String("found".into()));
+}
+
+#[test]
+fn and_and_or_are_natives_as_values() {
+    assert_eq!(run("asValues", vec![]).0, list(vec![Data::Bool(false), Data::Bool(true)]));
+}
+
+#[test]
+fn special_forms_cant_be_defined() {
+    for name in ["and", "or", "when", "unless", "cond", "case"] {
+        let source = format!("(defCont {name} [ret] (apply ret 1))");
+        let err = Program::parse(&source).unwrap_err().to_string();
+        assert!(err.contains("is a special form"), "{err}");
+    }
+}
@@ -0,0 +1,112 @@
+use continual::{
+    Data,
+    Program,
+    Status,
+};
+use common::{
+    interpreter,
+    load,
+    nums,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont twice [ret]
+    (apply ret (reset (add 1 (shift k (k (k 10)))))))
+
+(defCont abort [ret]
+    (apply ret (add 1 (reset (add 10 (shift k 5))))))
+
+(defCont product [ret]
+    (apply ret (reset (add 100 (shift k (mul (k 1) (k 2)))))))
+
+(defCont inner [ret]
+    (apply ret (shift k (k (k 10)))))
+(defCont acrossCalls [ret]
+    (apply ret (reset (add 1 (inner)))))
+
+(defCont saved [ret]
+    (set k (reset (add 1 (shift k k))))
+    (apply ret (k (k 1))))
+
+(defCont setInside [ret]
+    (set x 1)
+    (set y 1)
+    (reset (set x 2))
+    (handle (set y 3) (never [k] 0))
+    (apply ret (list x y)))
+(defCont callerVars [ret]
+    (set x 1)
+    (set k (reset (set x 10) (shift k k)))
+    (set y x)
+    (set x 2)
+    (k 0)
+    (apply ret (list y x)))
+(defCont noReturn [ret]
+    (set y 5))
+(defCont fallThrough [ret]
+    (set y 1)
+    (reset (noReturn))
+    (apply ret y))
+
+(defCont suspended [ret]
+    (apply ret (add 1 (reset (add 10 (yield-host 0))))))
+"#;
+
+
+#[test]
+fn continuations_compose() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    assert_eq!(interpreter.call("twice", ()).unwrap(), Data::Number(12));
+    assert_eq!(interpreter.call("product", ()).unwrap(), Data::Number(101 * 102));
+}
+
+#[test]
+fn shift_aborts_to_the_reset() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    assert_eq!(interpreter.call("abort", ()).unwrap(), Data::Number(6));
+}
+
+#[test]
+fn shift_captures_across_calls() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    assert_eq!(interpreter.call("acrossCalls", ()).unwrap(), Data::Number(12));
+}
+
+#[test]
+fn continuations_can_escape_their_reset() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    assert_eq!(interpreter.call("saved", ()).unwrap(), Data::Number(3));
+}
+
+#[test]
+fn resets_survive_suspension() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    let mut machine = interpreter.start("suspended", ()).unwrap();
+    let Status::Suspended(_, resumer) = machine.step(1000) else {
+        panic!("Expected the program to suspend");
+    };
+
+    let mut machine = interpreter.resume(resumer, Data::Number(5));
+    assert_eq!(machine.run().unwrap(), Data::Number(16));
+}
+
+#[test]
+fn variables_set_in_a_reset_are_kept() {
+    let interpreter = load(SOURCE);
+    assert_eq!(interpreter.call("setInside", ()).unwrap(), nums(&[2, 3]));
+    assert_eq!(interpreter.call("callerVars", ()).unwrap(), nums(&[10, 2]));
+    assert_eq!(interpreter.call("fallThrough", ()).unwrap(), Data::Number(1));
+}
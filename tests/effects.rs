@@ -0,0 +1,88 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+use continual::Data;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont greet [ret]
+    (perform log "hello")
+    (perform log "world")
+    (apply ret 3))
+
+(defCont logToHost [ret]
+    (apply ret (handle (greet)
+        (log [msg k] (record msg) (k #n)))))
+(defCont silent [ret]
+    (apply ret (handle (greet)
+        (log [msg k] (k #n)))))
+
+(defCont counter [ret]
+    (set s 0)
+    (apply ret (handle
+        (begin
+            (perform put (add (perform get) 1))
+            (perform put (add (perform get) 1))
+            (perform get))
+        (get [k] (k s))
+        (put [v k] (set s v) (k #n)))))
+
+(defCont nested [ret]
+    (apply ret (handle
+        (handle (add (perform ask) (perform double 5))
+            (double [n k] (k (mul n 2))))
+        (ask [k] (k 100)))))
+
+(defCont abort [ret]
+    (apply ret (handle (add 1 (perform fail))
+        (fail [k] 0))))
+
+(defCont multiShot [ret]
+    (apply ret (handle (add 1 (perform choose))
+        (choose [k] (add (k 10) (k 20))))))
+
+(defCont unhandled [ret]
+    (apply ret (try (perform missing 1) (catch [e] (error-message e)))))
+"#;
+
+
+fn run(name: &str)->(Data<'static>, Vec<String>) {
+    let log = Rc::new(RefCell::new(Vec::new()));
+
+    let mut interpreter = common::load(SOURCE);
+    let record = log.clone();
+    interpreter.register("record", move|msg: String|record.borrow_mut().push(msg));
+
+    let res = interpreter.call(name, ()).unwrap();
+    return (res, log.take());
+}
+
+#[test]
+fn handlers_can_be_swapped() {
+    assert_eq!(run("logToHost"), (Data::Number(3), vec!["hello".into(), "world".into()]));
+    assert_eq!(run("silent"), (Data::Number(3), vec![]));
+}
+
+#[test]
+fn state_as_an_effect() {
+    assert_eq!(run("counter").0, Data::Number(2));
+}
+
+#[test]
+fn performs_reach_outer_handlers() {
+    assert_eq!(run("nested").0, Data::Number(110));
+}
+
+#[test]
+fn handlers_can_abort_or_resume_many_times() {
+    assert_eq!(run("abort").0, Data::Number(0));
+    assert_eq!(run("multiShot").0, Data::Number(11 + 21));
+}
+
+#[test]
+fn unhandled_effects_are_errors() {
+    assert_eq!(run("unhandled").0, Data::String("No handler for the effect `missing`".into()));
+}
@@ -0,0 +1,98 @@
+use continual::{
+    Data,
+    ErrorKind,
+    ScriptError,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont quick [ret] (apply ret (add 1 2)))
+(defCont finishes [ret]
+    (apply ret (engine quick 1000)))
+
+(defCont spin [ret] (apply spin ret))
+(defCont timesOut [ret]
+    (apply ret (engine? (engine spin 100))))
+
+(defCont count [ret n acc]
+    (if (eq n 0) (apply ret acc))
+    (apply count ret (sub n 1) (add acc n)))
+(defCont sum100 [ret] (apply count ret 100 0))
+(defCont drive [ret e]
+    (log 0)
+    (set r (engine e 50))
+    (if (engine? r) (apply drive ret r))
+    (apply ret r))
+(defCont resumed [ret]
+    (apply ret (drive sum100)))
+
+(defCont loopLog [ret n tag]
+    (if (eq n 0) (apply ret tag))
+    (log tag)
+    (apply loopLog ret (sub n 1) tag))
+(defCont ones [ret] (apply loopLog ret 5 1))
+(defCont twos [ret] (apply loopLog ret 5 2))
+(defCont race [ret x y]
+    (set r (engine x 30))
+    (if (engine? r) (apply race ret y r))
+    (apply ret r))
+(defCont roundRobin [ret]
+    (apply ret (race ones twos)))
+
+(defCont innerSpin [ret]
+    (apply ret (engine spin 1000000)))
+(defCont outerStops [ret]
+    (apply ret (engine? (engine innerSpin 100))))
+(defCont innerStops [ret]
+    (apply ret (engine timesOut 1000)))
+
+(defCont negativeFuel [ret] (apply ret (engine quick -1)))
+(defCont notRunnable [ret] (apply ret (engine 5 100)))
+"#;
+
+
+fn run(name: &str)->(Data<'static>, Vec<i64>) {
+    common::run_logged(SOURCE, name, ())
+}
+
+#[test]
+fn engines_return_results() {
+    assert_eq!(run("finishes").0, Data::Number(3));
+}
+
+#[test]
+fn engines_run_out_of_fuel() {
+    assert_eq!(run("timesOut").0, Data::Bool(true));
+}
+
+#[test]
+fn engines_can_be_resumed() {
+    let (res, log) = run("resumed");
+    assert_eq!(res, Data::Number(5050));
+    assert!(log.len() > 1, "{log:?}");
+}
+
+#[test]
+fn engines_can_be_scheduled() {
+    let (res, log) = run("roundRobin");
+    assert_eq!(res, Data::Number(1));
+
+    let last_one = log.iter().rposition(|n|*n == 1).unwrap();
+    assert!(log[..last_one].contains(&2), "{log:?}");
+}
+
+#[test]
+fn nested_engines() {
+    assert_eq!(run("outerStops").0, Data::Bool(true));
+    assert_eq!(run("innerStops").0, Data::Bool(true));
+}
+
+#[test]
+fn engine_checks_its_args() {
+    for name in ["negativeFuel", "notRunnable"] {
+        let err = common::call_logged(SOURCE, name, ()).0.unwrap_err();
+        assert_eq!(err.downcast_ref::<ScriptError>().unwrap().kind, ErrorKind::Type, "{name}");
+    }
+}
@@ -0,0 +1,107 @@
+use continual::{
+    Data,
+    ErrorKind,
+    Program,
+    Uncaught,
+};
+use common::interpreter;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont thrown [ret]
+    (apply ret (try
+        (add 1 (throw "oops"))
+        (catch [e] (error-message e)))))
+
+(defCont kind [ret f]
+    (apply ret (try
+        (f)
+        (catch [e] (error-kind e)))))
+(defCont typeError [ret] (apply ret (add 1 "two")))
+(defCont unbound [ret] (apply ret missing))
+(defCont arity [ret] (apply ret (typeError 1)))
+(defCont runtime [ret] (apply ret (rem 1 0)))
+(defCont user [ret] (throw 5))
+(defCont throwArity [ret] (throw))
+
+(defCont value [ret]
+    (apply ret (try (throw 42) (catch [e] (error-value e)))))
+
+(defCont location [ret]
+    (try
+        (unbound)
+        (catch [e] (apply ret (add (mul 100 (error-line e)) (error-column e))))))
+
+(defCont rethrow [ret]
+    (apply ret (try
+        (try (throw "inner") (catch [e] (throw e)))
+        (catch [e] (error-message e)))))
+
+(defCont normal [ret]
+    (apply ret (try (add 1 2) (catch [e] 0))))
+
+(defCont acrossReset [ret]
+    (apply ret (try
+        (reset (add 1 (throw "deep")))
+        (catch [e] (error-message e)))))
+
+(defCont uncaught [ret]
+    (throw "nobody catches this"))
+"#;
+
+
+#[test]
+fn catch_thrown_values() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    assert_eq!(interpreter.call("thrown", ()).unwrap(), Data::String("oops".into()));
+    assert_eq!(interpreter.call("value", ()).unwrap(), Data::Number(42));
+    assert_eq!(interpreter.call("rethrow", ()).unwrap(), Data::String("inner".into()));
+    assert_eq!(interpreter.call("normal", ()).unwrap(), Data::Number(3));
+    assert_eq!(interpreter.call("acrossReset", ()).unwrap(), Data::String("deep".into()));
+}
+
+#[test]
+fn interpreter_errors_have_kinds() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    for (name, kind) in [
+        ("typeError", ErrorKind::Type),
+        ("unbound", ErrorKind::Unbound),
+        ("arity", ErrorKind::Arity),
+        ("throwArity", ErrorKind::Arity),
+        ("runtime", ErrorKind::Runtime),
+        ("user", ErrorKind::User),
+    ] {
+        let f = Data::Continuation(interpreter.get(name).unwrap());
+        let res = interpreter.call("kind", (f,)).unwrap();
+        assert_eq!(res, Data::String(kind.to_string()), "{name}");
+    }
+}
+
+#[test]
+fn errors_have_locations() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    // `missing` is on line 12, column 35 of the source
+    assert_eq!(interpreter.call("location", ()).unwrap(), Data::Number(1235));
+}
+
+#[test]
+fn uncaught_errors_stop_the_program() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program);
+
+    let err = interpreter.call("uncaught", ()).unwrap_err();
+    let uncaught = err.downcast_ref::<Uncaught>().unwrap();
+    assert_eq!(uncaught.kind, ErrorKind::User);
+    assert_eq!(uncaught.message, "nobody catches this");
+
+    let err = interpreter.call("typeError", ()).unwrap_err().to_string();
+    assert!(err.contains("Expected number, but got string"), "{err}");
+}
@@ -0,0 +1,100 @@
+use continual::Data;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont countUp [ret from to]
+    (if (eq from to) (apply ret #n))
+    (emit from)
+    (apply countUp ret (add from 1) to))
+(defGen range [ret from to]
+    (apply countUp ret from to))
+(defGen naturals [ret]
+    (apply countUp ret 0 (sub 0 1)))
+
+(defCont sumGen [ret g total]
+    (if (gen-done? g) (apply ret total))
+    (apply sumGen ret g (add total (next g))))
+(defCont sumRange [ret]
+    (apply ret (sumGen (range 1 5) 0)))
+
+(defGen noisy [ret]
+    (log 1)
+    (emit 10)
+    (log 2)
+    (emit 20)
+    (log 3))
+(defCont lazy [ret]
+    (set g (noisy))
+    (log 0)
+    (log (next g))
+    (log (next g))
+    (log 4)
+    (apply ret (if (eq (next g) #n) (gen-done? g) #f)))
+
+(defCont show [ret v]
+    (log v)
+    (apply ret #n))
+(defCont forEach [ret]
+    (apply ret (for-each (range 0 3) show)))
+
+(defCont infinite [ret]
+    (set g (naturals))
+    (next g)
+    (next g)
+    (apply ret (next g)))
+
+(defCont zip [ret]
+    (set a (range 0 3))
+    (set b (range 10 13))
+    (log (next a))
+    (log (next b))
+    (log (next a))
+    (log (next b))
+    (apply ret #n))
+
+(defCont outside [ret]
+    (apply ret (try (emit 1) (catch [e] (error-message e)))))
+
+(defCont nextNumber [ret]
+    (apply ret (try (next 5) (catch [e] (error-message e)))))
+"#;
+
+
+fn run(name: &str)->(Data<'static>, Vec<i64>) {
+    common::run_logged(SOURCE, name, ())
+}
+
+#[test]
+fn generators_produce_values() {
+    assert_eq!(run("sumRange").0, Data::Number(10));
+    assert_eq!(run("infinite").0, Data::Number(2));
+}
+
+#[test]
+fn generators_are_lazy() {
+    let (res, log) = run("lazy");
+    assert_eq!(res, Data::Bool(true));
+    assert_eq!(log, vec![0, 1, 10, 2, 20, 4, 3]);
+}
+
+#[test]
+fn for_each_visits_every_value() {
+    assert_eq!(run("forEach"), (Data::None, vec![0, 1, 2]));
+}
+
+#[test]
+fn generators_are_independent() {
+    assert_eq!(run("zip").1, vec![0, 10, 1, 11]);
+}
+
+#[test]
+fn emit_needs_a_generator() {
+    assert_eq!(run("outside").0, Data::String("`emit` can only be used inside a generator".into()));
+}
+
+#[test]
+fn next_needs_a_generator() {
+    assert_eq!(run("nextNumber").0, Data::String("Argument 1 of `next`: Expected generator, but got number".into()));
+}
@@ -0,0 +1,107 @@
+use continual::{
+    Data,
+    Interpreter,
+    LimitExceeded,
+    Limits,
+    Program,
+    Rest,
+};
+use std::time::Duration;
+
+
+const SOURCE: &str = r#"
+(defCont forever [ret]
+    (apply forever ret))
+
+(defCont sum [ret n]
+    (if (eq n 0) (apply ret 0))
+    (apply ret (add n (sum (sub n 1)))))
+
+(defCont grow [ret s]
+    (apply grow ret (concat s s)))
+
+(defCont evalEach [ret n]
+    (eval (list 'add n 1))
+    (apply evalEach ret (add n 1)))
+"#;
+
+
+fn interpreter<'a>(program: &'a Program<'a>, limits: Limits)->Interpreter<'a> {
+    let mut interpreter = Interpreter::new();
+    interpreter.load(program).unwrap();
+    interpreter.register("concat", |parts: Rest<String>|parts.0.concat());
+    interpreter.set_limits(limits);
+
+    return interpreter;
+}
+
+fn limit_error(res: anyhow::Result<Data>)->LimitExceeded {
+    let err = res.unwrap_err();
+    return *err.downcast_ref::<LimitExceeded>().unwrap_or_else(||panic!("Not a limit error: {err}"));
+}
+
+
+#[test]
+fn steps() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program, Limits {steps: Some(10_000), ..Limits::default()});
+
+    assert_eq!(limit_error(interpreter.call("forever", ())), LimitExceeded::Steps(10_000));
+    // the limit is per machine, so finishing in time is fine
+    assert_eq!(interpreter.call("sum", (10,)).unwrap(), Data::Number(55));
+}
+
+#[test]
+fn time() {
+    let program = Program::parse(SOURCE).unwrap();
+    let time = Duration::from_millis(50);
+    let interpreter = interpreter(&program, Limits {time: Some(time), ..Limits::default()});
+
+    assert_eq!(limit_error(interpreter.call("forever", ())), LimitExceeded::Time(time));
+}
+
+#[test]
+fn depth() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program, Limits {depth: Some(100), ..Limits::default()});
+
+    assert_eq!(interpreter.call("sum", (10,)).unwrap(), Data::Number(55));
+    assert_eq!(limit_error(interpreter.call("sum", (1000,))), LimitExceeded::Depth(100));
+}
+
+#[test]
+fn string_length() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program, Limits {string_length: Some(1000), ..Limits::default()});
+
+    assert_eq!(limit_error(interpreter.call("grow", ("ab",))), LimitExceeded::StringLength(1000));
+}
+
+#[test]
+fn data_size() {
+    let program = Program::parse(SOURCE).unwrap();
+    let interpreter = interpreter(&program, Limits {data_size: Some(10_000), ..Limits::default()});
+    assert_eq!(limit_error(interpreter.call("grow", ("ab",))), LimitExceeded::DataSize(10_000));
+
+    // deep recursion keeps a lot of frames and continuations alive
+    assert_eq!(limit_error(interpreter.call("sum", (100_000,))), LimitExceeded::DataSize(10_000));
+}
+
+#[test]
+fn evaluated_code_counts_towards_the_data_size() {
+    let program = Program::parse(SOURCE).unwrap();
+    let limits = Limits {data_size: Some(10_000), steps: Some(1_000_000), ..Limits::default()};
+    let interpreter = interpreter(&program, limits);
+
+    // nothing but the evaluated code is kept from one call to the next
+    assert_eq!(limit_error(interpreter.call("evalEach", (0,))), LimitExceeded::DataSize(10_000));
+}
+
+#[test]
+fn other_errors_are_not_limits() {
+    let program = Program::parse("(defCont main [ret] (apply ret (missing)))").unwrap();
+    let interpreter = interpreter(&program, Limits {steps: Some(10), ..Limits::default()});
+
+    let err = interpreter.call("main", ()).unwrap_err();
+    assert!(err.downcast_ref::<LimitExceeded>().is_none());
+}
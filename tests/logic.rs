@@ -0,0 +1,125 @@
+use continual::Data;
+use common::{
+    list,
+    nums,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont choice [ret q]
+    (apply ret (conde (== q 1) (== q 2) (== q 3))))
+(defCont firstTwo [ret]
+    (apply ret (run 2 choice)))
+(defCont every [ret]
+    (apply ret (run #n choice)))
+
+(defCont appendo [ret l s out]
+    (apply ret (conde
+        (list (== l (list)) (== s out))
+        (fresh 3 appendoPair l s out))))
+(defCont appendoPair [ret l s out a d res]
+    (apply ret (all
+        (== l (list a d))
+        (== out (list a res))
+        (appendo d s res))))
+(defCont split [ret q]
+    (apply ret (fresh 2 splitInto q)))
+(defCont splitInto [ret q x y]
+    (apply ret (list
+        (== q (list x y))
+        (appendo x y (list 1 (list 2 (list)))))))
+(defCont splits [ret]
+    (apply ret (run #n split)))
+
+(defCont nat [ret n]
+    (apply ret (conde
+        (== n 0)
+        (fresh 1 natSucc n))))
+(defCont natSucc [ret n m]
+    (apply ret (list (== n (list 1 m)) (nat m))))
+(defCont naturals [ret]
+    (apply ret (run 3 nat)))
+(defCont natOr [ret q]
+    (apply ret (conde (nat q) (== q 99))))
+(defCont fair [ret]
+    (apply ret (run 3 natOr)))
+
+(defCont pairOf [ret q]
+    (apply ret (fresh 2 pairUp q)))
+(defCont pairUp [ret q a b]
+    (apply ret (== q (list a b a))))
+(defCont unbound [ret]
+    (apply ret (run 1 pairOf)))
+
+(defCont cyclic [ret q]
+    (apply ret (== q (list q))))
+(defCont clash [ret q]
+    (apply ret (list (== q 1) (== q 2))))
+(defCont failures [ret]
+    (apply ret (list (run #n cyclic) (run #n clash))))
+
+(defCont kind [ret f]
+    (apply ret (try (f) (catch [e] (error-kind e)))))
+(defCont negativeFresh [ret] (apply ret (fresh -1 pairOf)))
+(defCont negativeRun [ret] (apply ret (run -1 pairOf)))
+(defCont freshNothing [ret] (apply ret (fresh 1)))
+(defCont badErrors [ret]
+    (apply ret (list (kind negativeFresh) (kind negativeRun) (kind freshNothing))))
+"#;
+
+
+fn run(name: &str)->Data<'static> {
+    common::run(SOURCE, name, ())
+}
+
+/// A list of pairs, like `(list a (list b (list)))`.
+fn pairs(items: &[i64])->Data<'static> {
+    items.iter().rev().fold(list(vec![]), |rest, n|list(vec![Data::Number(*n), rest]))
+}
+
+#[test]
+fn run_finds_answers() {
+    assert_eq!(run("firstTwo"), nums(&[1, 2]));
+    assert_eq!(run("every"), nums(&[1, 2, 3]));
+}
+
+#[test]
+fn recursive_relations() {
+    let expected = list(vec![
+        list(vec![pairs(&[]), pairs(&[1, 2])]),
+        list(vec![pairs(&[1]), pairs(&[2])]),
+        list(vec![pairs(&[1, 2]), pairs(&[])]),
+    ]);
+    assert_eq!(run("splits"), expected);
+}
+
+#[test]
+fn search_interleaves() {
+    let zero = Data::Number(0);
+    let one = list(vec![Data::Number(1), zero.clone()]);
+    let two = list(vec![Data::Number(1), one.clone()]);
+    assert_eq!(run("naturals"), list(vec![zero.clone(), one.clone(), two]));
+
+    let res = run("fair");
+    let Data::List(answers) = &res else {panic!("{res:?}")};
+    assert!(answers.contains(&Data::Number(99)), "{res:?}");
+}
+
+#[test]
+fn unbound_variables_are_reified() {
+    let name = |s: &str|Data::Symbol(s.into());
+    assert_eq!(run("unbound"), list(vec![list(vec![name("_.0"), name("_.1"), name("_.0")])]));
+}
+
+#[test]
+fn failed_unification() {
+    assert_eq!(run("failures"), list(vec![nums(&[]), nums(&[])]));
+}
+
+#[test]
+fn bad_args() {
+    let kinds = ["type", "type", "arity"].map(|kind|Data::String(kind.into()));
+    assert_eq!(run("badErrors"), list(kinds.to_vec()));
+}
@@ -0,0 +1,138 @@
+use continual::{
+    Data,
+    Interpreter,
+    Program,
+    Status,
+};
+
+
+const SOURCE: &str = r#"
+(defCont sum [ret n]
+    (if (eq n 0) (apply ret 0))
+    (apply ret (add n (sum (sub n 1)))))
+
+(defCont saveRet [ret] (apply ret ret))
+(defCont reenter [ret]
+    (set x (saveRet))
+    (if (eq x 10) (apply ret x))
+    (apply x 10))
+
+(defCont escape [ret]
+    (add 1 (letcc k (apply k 41))))
+
+(defCont forever [ret]
+    (apply forever ret))
+
+(defCont broken [ret]
+    (apply ret (missing 1)))
+"#;
+
+
+#[test]
+fn step_until_finished() {
+    let program = Program::parse(SOURCE).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    let mut machine = interpreter.start("sum", (100,)).unwrap();
+    let mut slices = 0;
+    let res = loop {
+        slices += 1;
+        match machine.step(10) {
+            Status::Running=>{},
+            Status::Finished(data)=>break data,
+            s=>panic!("Expected to finish, but got {s:?}"),
+        }
+    };
+
+    assert_eq!(res, Data::Number(5050));
+    assert!(slices > 1);
+    assert!(machine.is_halted());
+    assert!(matches!(machine.step(1), Status::Error(_)));
+}
+
+#[test]
+fn infinite_loops_can_be_stepped() {
+    let program = Program::parse(SOURCE).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    let mut machine = interpreter.start("forever", ()).unwrap();
+    for _ in 0..100 {
+        assert!(matches!(machine.step(1000), Status::Running));
+    }
+    assert_eq!(machine.steps(), 100_000);
+}
+
+#[test]
+fn errors_halt_the_machine() {
+    let program = Program::parse(SOURCE).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    let mut machine = interpreter.start("broken", ()).unwrap();
+    match machine.step(100) {
+        Status::Error(e)=>assert!(e.to_string().contains("missing"), "{e}"),
+        s=>panic!("Expected an error, but got {s:?}"),
+    }
+    assert!(machine.is_halted());
+}
+
+#[test]
+fn deep_recursion() {
+    let program = Program::parse(SOURCE).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    assert_eq!(interpreter.call("sum", (20_000,)).unwrap(), Data::Number(200_010_000));
+}
+
+#[test]
+fn saved_return_continuation() {
+    let program = Program::parse(SOURCE).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    assert_eq!(interpreter.call("reenter", ()).unwrap(), Data::Number(10));
+}
+
+#[test]
+fn letcc_escape() {
+    let program = Program::parse(SOURCE).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    assert_eq!(interpreter.call("escape", ()).unwrap(), Data::Number(42));
+}
+
+#[test]
+fn yield_to_host() {
+    let program = Program::parse(r#"
+        (defCont main [ret]
+            (set a (yield-host "first"))
+            (set b (yield-host a))
+            (apply ret (add a b)))
+    "#).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.load(&program).unwrap();
+
+    let mut machine = interpreter.start("main", ()).unwrap();
+    let Status::Suspended(data, resumer) = machine.step(1000) else {panic!("Expected to suspend")};
+    assert_eq!(data, Data::String("first".into()));
+    assert!(machine.is_halted());
+
+    // a resumer can be resumed more than once
+    let mut machine = interpreter.resume(resumer.clone(), Data::Number(1));
+    let Status::Suspended(data, _) = machine.step(1000) else {panic!("Expected to suspend")};
+    assert_eq!(data, Data::Number(1));
+
+    let mut machine = interpreter.resume(resumer, Data::Number(20));
+    let Status::Suspended(data, resumer) = machine.step(1000) else {panic!("Expected to suspend")};
+    assert_eq!(data, Data::Number(20));
+
+    let mut machine = interpreter.resume(resumer, Data::Number(22));
+    assert!(matches!(machine.step(1000), Status::Finished(Data::Number(42))));
+
+    // running to completion can't hand anything to the host
+    assert!(interpreter.call("main", ()).is_err());
+}
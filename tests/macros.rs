@@ -0,0 +1,84 @@
+use std::rc::Rc;
+use continual::{
+    parser::Parser,
+    CodeArena,
+    Data,
+    Program,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defMacro swap! [a b]
+    `(begin
+        (set tmp ,a)
+        (set ,a ,b)
+        (set ,b tmp)))
+(defMacro my-unless [cond &rest body]
+    `(if ,cond #n (begin ,@body)))
+(defMacro twice [e]
+    `(begin ,e ,e))
+(defMacro four-times [e]
+    `(twice (twice ,e)))
+
+(defCont swapped [ret]
+    (set tmp 1)
+    (set other 2)
+    (swap! tmp other)
+    (apply ret (list tmp other)))
+
+(defCont unlessLogs [ret x]
+    (my-unless (eq x 0)
+        (log 1)
+        (log 2))
+    (apply ret #n))
+
+(defCont nested [ret]
+    (four-times (log 3))
+    (apply ret #n))
+"#;
+
+
+fn run(name: &str, args: Vec<Data<'static>>)->(Data<'static>, Vec<i64>) {
+    common::run_logged(SOURCE, name, args)
+}
+
+fn parse_error(source: &'static str)->String {
+    Program::parse(source).unwrap_err().to_string()
+}
+
+#[test]
+fn macros_expand_to_code() {
+    assert_eq!(run("unlessLogs", vec![Data::Number(0)]).1, vec![]);
+    assert_eq!(run("unlessLogs", vec![Data::Number(1)]).1, vec![1, 2]);
+    assert_eq!(run("nested", vec![]).1, vec![3, 3, 3, 3]);
+}
+
+#[test]
+fn macro_variables_dont_capture() {
+    let list = Data::List(Rc::new(vec![Data::Number(2), Data::Number(1)]));
+    assert_eq!(run("swapped", vec![]).0, list);
+}
+
+#[test]
+fn expansions_can_be_inspected() {
+    let code = CodeArena::default();
+    let expansions = Parser::new(SOURCE, &code).expansions().unwrap();
+    let shown: Vec<_> = expansions.iter()
+        .map(|(from, to)|format!("{from} => {to}"))
+        .collect();
+    assert_eq!(shown[0], "(swap! tmp other) => (begin (set tmp#1 tmp) (set tmp other) (set other tmp#1))");
+    assert_eq!(shown[1], "(my-unless (eq x 0) (log 1) (log 2)) => (if (eq x 0) #n (begin (log 1) (log 2)))");
+    assert_eq!(shown.len(), 6);
+}
+
+#[test]
+fn bad_macros() {
+    assert!(parse_error("(defMacro m [a] `,a) (defCont f [ret] (m))").contains("The macro `m` expects 1 args, but got 0"));
+    assert!(parse_error("(defMacro m [] `(m)) (defCont f [ret] (m))").contains("didn't stop"));
+    assert!(parse_error("(defMacro m [] `(begin (m))) (defCont f [ret] (m))").contains("didn't stop"));
+    assert!(parse_error("(defMacro m [] (add 1 2))").contains("has to be a quasiquoted template"));
+    assert!(parse_error("(defCont f [ret] (defMacro m [] `1))").contains("top level"));
+    assert!(parse_error("(defMacro m [a] `,b) (defCont f [ret] (m 1))").contains("Only the parameters of a macro can be unquoted"));
+}
@@ -0,0 +1,85 @@
+use std::rc::Rc;
+use continual::{
+    Data,
+    Limits,
+};
+use common::nums;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont inner [ret]
+    (apply ret (current-cont-marks "f")))
+(defCont middle [ret]
+    (apply ret (with-cont-mark "f" 2 (inner))))
+(defCont nested [ret]
+    (apply ret (with-cont-mark "f" 1 (middle))))
+
+(defCont countdown [ret n]
+    (with-cont-mark "n" n
+        (if (eq n 0) (apply ret (current-cont-marks "n")))
+        (apply countdown ret (sub n 1))))
+(defCont tail [ret]
+    (apply countdown ret 1000))
+
+(defCont countup [ret n]
+    (if (eq n 0) (apply ret (current-cont-marks "n")))
+    (apply ret (with-cont-mark "n" n (countup (sub n 1)))))
+(defCont nonTail [ret]
+    (apply countup ret 3))
+
+(defCont keys [ret]
+    (apply ret (with-cont-mark "a" 1
+        (with-cont-mark "b" 2
+            (with-cont-mark "a" 3
+                (list (current-cont-marks "a") (current-cont-marks "b")))))))
+
+(defCont reenter [ret]
+    (set res (with-cont-mark "who" 7
+        (set r (letcc c c))
+        (list r (current-cont-marks "who"))))
+    (log (length (current-cont-marks "who")))
+    (set r (nth res 0))
+    (if (eq r 1) (apply ret (nth res 1)))
+    (apply r 1))
+
+(defCont noKey [ret]
+    (apply ret (try (current-cont-marks) (catch [e] (error-kind e)))))
+"#;
+
+
+fn run(name: &str)->(Data<'static>, Vec<i64>) {
+    let mut interpreter = common::load(SOURCE);
+    interpreter.set_limits(Limits {depth: Some(50), ..Limits::default()});
+    let log = common::log(&mut interpreter);
+
+    let res = interpreter.call(name, ()).unwrap();
+    return (res, log.take());
+}
+
+#[test]
+fn marks_are_read_innermost_first() {
+    assert_eq!(run("nested").0, nums(&[2, 1]));
+    assert_eq!(run("nonTail").0, nums(&[1, 2, 3]));
+}
+
+#[test]
+fn tail_marks_replace_each_other() {
+    assert_eq!(run("tail").0, nums(&[0]));
+}
+
+#[test]
+fn marks_with_different_keys() {
+    assert_eq!(run("keys").0, Data::List(Rc::new(vec![nums(&[3]), nums(&[2])])));
+}
+
+#[test]
+fn marks_survive_reentry() {
+    assert_eq!(run("reenter"), (nums(&[7]), vec![0, 0]));
+}
+
+#[test]
+fn marks_need_a_key() {
+    assert_eq!(run("noKey").0, Data::String("arity".into()));
+}
@@ -0,0 +1,163 @@
+use continual::{
+    parser::Parser,
+    CodeArena,
+    Data,
+};
+use common::nums;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont fizzBuzz [ret n]
+    (apply ret (match (list (rem n 3) (rem n 5))
+        ([0 0] "FizzBuzz")
+        ([0 _] "Fizz")
+        ([_ 0] "Buzz")
+        (_ n))))
+
+(defCont describe [ret v]
+    (apply ret (match v
+        (#n "nothing")
+        (#t "yes")
+        (:ok "fine")
+        ("hi" "greeting")
+        (0 "zero")
+        ([] "empty")
+        ([x] (add x 1))
+        ([x y &rest more] (add (add x y) (length more)))
+        (_ "other"))))
+
+(defCont sum [ret xs]
+    (apply ret (match xs
+        ([] 0)
+        ([x &rest more] (add x (sum more))))))
+(defCont restOfRest [ret xs]
+    (apply ret (match xs
+        ([a &rest [b &rest [c &rest more]]] (list a b c (length more)))
+        ([a &rest _] (list a)))))
+(defCont sumNested [ret]
+    (apply ret (match (list 1 (list 2 3) 4)
+        ([a [b c] d] (sum (list a b c d))))))
+
+(defCont classify [ret n]
+    (apply ret (match n
+        (0 "zero")
+        ([x y] :when (eq x y) "pair")
+        ([x _] "other pair")
+        (x :when (eq (rem x 2) 0) "even")
+        (_ "odd"))))
+
+(defCont scoped [ret]
+    (set x 1)
+    (match 2
+        (x (log x)))
+    (apply ret x))
+
+(defCont noMatch [ret]
+    (apply ret (try
+        (match (list 1 2) ([a] a) (0 0))
+        (catch [e] (error-message e)))))
+"#;
+
+
+fn run(name: &str, args: Vec<Data<'static>>)->Data<'static> {
+    let mut interpreter = common::load(SOURCE);
+    interpreter.register("log", |n: i64|assert_eq!(n, 2));
+
+    return interpreter.call(name, args).unwrap();
+}
+
+#[test]
+fn literal_and_wildcard_patterns() {
+    let results: Vec<_> = [3, 5, 15, 7]
+        .into_iter()
+        .map(|n|run("fizzBuzz", vec![Data::Number(n)]))
+        .collect();
+    assert_eq!(results, vec![
+        Data::String("Fizz".into()),
+        Data::String("Buzz".into()),
+        Data::String("FizzBuzz".into()),
+        Data::Number(7),
+    ]);
+
+    assert_eq!(run("describe", vec![Data::None]), Data::String("nothing".into()));
+    assert_eq!(run("describe", vec![Data::Bool(true)]), Data::String("yes".into()));
+    assert_eq!(run("describe", vec![Data::Keyword("ok")]), Data::String("fine".into()));
+    assert_eq!(run("describe", vec![Data::String("hi".into())]), Data::String("greeting".into()));
+    assert_eq!(run("describe", vec![Data::Number(0)]), Data::String("zero".into()));
+    assert_eq!(run("describe", vec![Data::Number(1)]), Data::String("other".into()));
+}
+
+#[test]
+fn list_patterns() {
+    assert_eq!(run("describe", vec![nums(&[])]), Data::String("empty".into()));
+    assert_eq!(run("describe", vec![nums(&[4])]), Data::Number(5));
+    assert_eq!(run("describe", vec![nums(&[4, 5, 6, 7])]), Data::Number(11));
+    assert_eq!(run("sum", vec![nums(&[1, 2, 3, 4])]), Data::Number(10));
+    assert_eq!(run("sumNested", vec![]), Data::Number(10));
+    assert_eq!(run("restOfRest", vec![nums(&[1, 2, 3, 4, 5])]), nums(&[1, 2, 3, 2]));
+    assert_eq!(run("restOfRest", vec![nums(&[1, 2])]), nums(&[1]));
+}
+
+#[test]
+fn guards() {
+    let classify = |n: Data<'static>|run("classify", vec![n]);
+    let pair = |a, b|Data::List(vec![Data::Number(a), Data::Number(b)].into());
+    assert_eq!(classify(Data::Number(0)), Data::String("zero".into()));
+    assert_eq!(classify(Data::Number(4)), Data::String("even".into()));
+    assert_eq!(classify(Data::Number(5)), Data::String("odd".into()));
+    assert_eq!(classify(pair(2, 2)), Data::String("pair".into()));
+    assert_eq!(classify(pair(2, 3)), Data::String("other pair".into()));
+}
+
+#[test]
+fn bindings_are_local_to_the_clause() {
+    assert_eq!(run("scoped", vec![]), Data::Number(1));
+}
+
+#[test]
+fn no_matching_clause_is_an_error() {
+    assert_eq!(run("noMatch", vec![]), Data::String("No `match` clause matched the list".into()));
+}
+
+/// The warnings for a `match` on `x` with the clauses.
+fn warnings(clauses: &str)->Vec<String> {
+    let source = format!("(defCont f [ret x] (apply ret (match x {clauses})))");
+    let code = CodeArena::default();
+    let mut parser = Parser::new(&source, &code);
+    parser.parse().unwrap();
+
+    return parser.warnings().iter().map(|w|w.to_string()).collect();
+}
+
+#[test]
+fn exhaustive_matches_have_no_warnings() {
+    assert!(warnings("(_ 0)").is_empty());
+    assert!(warnings("(#t 1) (#f 0)").is_empty());
+    assert!(warnings("([] 0) ([x &rest xs] 1)").is_empty());
+    assert!(warnings("([] 0) ([#t] 1) ([#f] 2) ([a b &rest c] 3)").is_empty());
+    assert!(warnings("([#t _] 1) ([_ #t] 2) ([#f #f] 3) (_ 4)").is_empty());
+    assert!(warnings("([a &rest [b &rest c]] 0) ([x] 1) ([] 2)").is_empty());
+
+    // the clause `case` adds for values without a datum covers them
+    let code = CodeArena::default();
+    let mut parser = Parser::new("(defCont f [ret] (apply ret (case 1 ((1) 2))))", &code);
+    parser.parse().unwrap();
+    assert!(parser.warnings().is_empty());
+}
+
+#[test]
+fn non_exhaustive_matches_warn() {
+    let missing = |clauses: &str|{
+        let warnings = warnings(clauses);
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        warnings[0].clone()
+    };
+
+    assert!(missing("(#t 1)").contains("like `#f`"));
+    assert!(missing("(0 1) (1 2)").contains("Add a `_` clause"));
+    assert!(missing("([] 0) ([x] 1)").contains("like `[_ _]`"));
+    assert!(missing("([#t _] 1) ([_ #t] 2) ([] 3) ([_] 4) ([_ _ _ &rest _] 5)").contains("like `[#f #f]`"));
+    assert!(missing("(x :when (eq x 1) 1)").contains("Add a `_` clause"));
+}
@@ -0,0 +1,76 @@
+use continual::{
+    Data,
+    Program,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defParam depth 0)
+
+(defCont showDepth [ret]
+    (log depth)
+    (apply ret depth))
+
+(defCont nested [ret]
+    (showDepth)
+    (parameterize [depth 1]
+        (showDepth)
+        (parameterize [depth (add depth 1)]
+            (showDepth))
+        (showDepth))
+    (apply ret (showDepth)))
+
+(defCont tail [ret]
+    (parameterize [depth 3]
+        (apply showDepth ret)))
+
+(defCont reenter [ret]
+    (set k (parameterize [depth 5]
+        (set r (letcc c c))
+        (log depth)
+        r))
+    (log depth)
+    (if (eq k 1) (apply ret #n))
+    (apply k 1))
+
+(defCont shadowed [ret depth]
+    (apply ret depth))
+(defCont local [ret]
+    (apply ret (parameterize [depth 7] (shadowed 8))))
+
+(defCont unknown [ret]
+    (apply ret (try (parameterize [width 1] #n) (catch [e] (error-message e)))))
+"#;
+
+
+fn run(name: &str)->(Data<'static>, Vec<i64>) {
+    common::run_logged(SOURCE, name, ())
+}
+
+#[test]
+fn parameterize_binds_for_its_body() {
+    assert_eq!(run("nested"), (Data::Number(0), vec![0, 1, 2, 1, 0]));
+}
+
+#[test]
+fn tail_calls_keep_the_binding() {
+    assert_eq!(run("tail"), (Data::Number(3), vec![3]));
+}
+
+#[test]
+fn reentered_continuations_see_the_binding() {
+    assert_eq!(run("reenter").1, vec![5, 0, 5, 0]);
+}
+
+#[test]
+fn variables_shadow_parameters() {
+    assert_eq!(run("local").0, Data::Number(8));
+}
+
+#[test]
+fn parameters_must_be_defined() {
+    assert_eq!(run("unknown").0, Data::String("No parameter with the name `width`".into()));
+    assert!(Program::parse("(defParam width (add 1 2))").is_err());
+}
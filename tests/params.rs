@@ -0,0 +1,105 @@
+use continual::{
+    Data,
+    Program,
+};
+use common::{
+    list,
+    nums,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont count [ret first &rest xs]
+    (apply ret (list first (length xs))))
+(defCont counts [ret]
+    (apply ret (list (count 1) (count 1 2 3))))
+
+(defCont add3 [ret a (b 10) (c (mul b 2))]
+    (apply ret (list a b c)))
+(defCont defaults [ret]
+    (apply ret (list (add3 1) (add3 1 2) (add3 1 2 3))))
+
+(defCont keywords [ret]
+    (apply ret (list (add3 1 :c 5) (add3 :b 3 :a 1) (add3 :a 1 2))))
+
+(defCont keywordValues [ret]
+    (set k :c)
+    (apply ret (list (add3 k 5) (list 1 :a 2) (add3 1 2 :c))))
+
+(defCont everything [ret a (b 0) &rest xs]
+    (apply ret (list a b xs)))
+(defCont mixed [ret]
+    (apply ret (everything 1 :b 2 3 4)))
+
+(defCont message [ret f]
+    (apply ret (try (f) (catch [e] (error-message e)))))
+(defCont tooFew [ret] (apply ret (add3)))
+(defCont tooMany [ret] (apply ret (add3 1 2 3 4)))
+(defCont tooManyNamed [ret] (apply ret (add3 1 2 3 :a 4)))
+(defCont unknown [ret] (apply ret (add3 1 :d 4)))
+(defCont twice [ret] (apply ret (add3 1 :b 2 :b 3)))
+
+(defGen upTo [ret (n 3) &rest ignored]
+    (emit n)
+    (emit (add n 1)))
+(defCont generated [ret]
+    (set g (upTo :n 5))
+    (set first (next g))
+    (apply ret (list first (next g))))
+"#;
+
+
+fn run(name: &str)->Data<'static> {
+    common::run(SOURCE, name, ())
+}
+
+fn message(name: &'static str)->Data<'static> {
+    let interpreter = common::load(SOURCE);
+    let f = interpreter.get(name).unwrap();
+
+    return interpreter.call("message", (f,)).unwrap();
+}
+
+#[test]
+fn rest_parameters() {
+    assert_eq!(run("counts"), list(vec![nums(&[1, 0]), nums(&[1, 2])]));
+}
+
+#[test]
+fn optional_parameters() {
+    assert_eq!(run("defaults"), list(vec![nums(&[1, 10, 20]), nums(&[1, 2, 4]), nums(&[1, 2, 3])]));
+}
+
+#[test]
+fn keyword_arguments() {
+    assert_eq!(run("keywords"), list(vec![nums(&[1, 10, 5]), nums(&[1, 3, 6]), nums(&[1, 2, 4])]));
+    assert_eq!(run("mixed"), list(vec![Data::Number(1), Data::Number(2), nums(&[3, 4])]));
+    assert_eq!(run("generated"), nums(&[5, 6]));
+}
+
+#[test]
+fn keyword_values_are_positional() {
+    let c = Data::Keyword("c");
+    assert_eq!(run("keywordValues"), list(vec![
+        list(vec![c.clone(), Data::Number(5), Data::Number(10)]),
+        list(vec![Data::Number(1), Data::Keyword("a"), Data::Number(2)]),
+        list(vec![Data::Number(1), Data::Number(2), c]),
+    ]));
+}
+
+#[test]
+fn arity_errors_describe_the_parameters() {
+    let text = |s: &str|Data::String(s.into());
+    assert_eq!(message("tooFew"), text("Expected 2 to 4 args for [ret a (b) (c)], but got 1"));
+    assert_eq!(message("tooMany"), text("Expected 2 to 4 args for [ret a (b) (c)], but got 5"));
+    assert_eq!(message("tooManyNamed"), text("Expected 2 to 4 args for [ret a (b) (c)], but got 4"));
+    assert_eq!(message("unknown"), text("Unknown keyword `:d` for [ret a (b) (c)]"));
+    assert_eq!(message("twice"), text("The keyword `:b` was given twice"));
+}
+
+#[test]
+fn optional_parameters_come_last() {
+    assert!(Program::parse("(defCont f [ret (a 1) b] (apply ret a))").is_err());
+}
@@ -0,0 +1,159 @@
+use continual::{
+    Capabilities,
+    Capability,
+    Data,
+    Interpreter,
+    Program,
+    Symbol,
+};
+use common::list;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont symbols [ret]
+    (apply ret (list
+        (eq 'red 'red)
+        (eq 'red 'blue)
+        (eq (symbol "red") 'red)
+        (symbol? 'red)
+        (symbol? "red")
+        (symbol-name 'red))))
+
+(defCont quoted [ret]
+    (apply ret (list
+        '(add 1 (mul 2 3))
+        (quote [a :b "c" #t #n])
+        ''x)))
+
+(defCont evaluated [ret]
+    (set code (list 'mul 6 7))
+    (apply ret (list
+        (eval '(add 1 2))
+        (eval code)
+        (eval (list 'add (sub 0 5) 1))
+        (eval (list (symbol "add") 2 3))
+        (eval '(try (throw 9) (catch [e] (error-value e)))))))
+
+(defCont negatives [ret]
+    (set min -9223372036854775808)
+    (apply ret (list
+        '(-1 2)
+        (eval (list 'add -5 1))
+        (eval (list 'quote (list min)))
+        (eq (eval min) min))))
+
+(defCont local [ret]
+    (set x 1)
+    (apply ret (try (eval 'x) (catch [e] (error-message e)))))
+(defCont noCode [ret]
+    (apply ret (try (eval) (catch [e] (error-message e)))))
+
+(defCont color [ret c]
+    (apply ret (match c
+        ('red 1)
+        ('green 2)
+        (_ 0))))
+(defCont colors [ret]
+    (apply ret (list
+        (color 'green)
+        (color 'blue)
+        (case 'red
+            (('red 'orange) "warm")
+            (else "cold")))))
+"#;
+
+
+fn run(name: &str)->Data<'static> {
+    common::run(SOURCE, name, ())
+}
+
+#[test]
+fn symbols_are_compared_by_name() {
+    assert_eq!(run("symbols"), list(vec![
+        Data::Bool(true),
+        Data::Bool(false),
+        Data::Bool(true),
+        Data::Bool(true),
+        Data::Bool(false),
+        Data::String("red".into()),
+    ]));
+}
+
+#[test]
+fn symbols_are_interned() {
+    let a = Symbol::new("interned");
+    let b: Symbol = String::from("interned").into();
+    assert_eq!(a, b);
+    assert!(std::ptr::eq(a.name(), b.name()));
+    assert_ne!(a, Symbol::new("other"));
+}
+
+#[test]
+fn quoted_code_is_data() {
+    let add = list(vec![
+        Data::Symbol("add".into()),
+        Data::Number(1),
+        list(vec![Data::Symbol("mul".into()), Data::Number(2), Data::Number(3)]),
+    ]);
+    let square = list(vec![
+        Data::Symbol("[]".into()),
+        Data::Symbol("a".into()),
+        Data::Keyword("b"),
+        Data::String("c".into()),
+        Data::Bool(true),
+        Data::None,
+    ]);
+    let quote = list(vec![Data::Symbol("quote".into()), Data::Symbol("x".into())]);
+    assert_eq!(run("quoted"), list(vec![add, square, quote]));
+}
+
+#[test]
+fn eval_runs_data_as_code() {
+    assert_eq!(run("evaluated"), list(vec![
+        Data::Number(3),
+        Data::Number(42),
+        Data::Number(-4),
+        Data::Number(5),
+        Data::Number(9),
+    ]));
+    assert_eq!(run("local"), Data::String("No variable with the name `x`".into()));
+    assert_eq!(run("noCode"), Data::String("`eval` expects 1 args, but got 0".into()));
+}
+
+#[test]
+fn negative_numbers_round_trip() {
+    assert_eq!(run("negatives"), list(vec![
+        list(vec![Data::Number(-1), Data::Number(2)]),
+        Data::Number(-4),
+        list(vec![Data::Number(i64::MIN)]),
+        Data::Bool(true),
+    ]));
+    assert!(Program::parse("(defCont f [ret] (apply ret 9223372036854775808))").is_err());
+}
+
+#[test]
+fn symbols_as_tags() {
+    assert_eq!(run("colors"), list(vec![Data::Number(2), Data::Number(0), Data::String("warm".into())]));
+}
+
+#[test]
+fn eval_needs_a_capability() {
+    let program = Box::leak(Box::new(Program::parse("(defCont f [ret] (apply ret (eval '1)))").unwrap()));
+    let mut interpreter = Interpreter::new();
+    let err = interpreter.load_with_capabilities(program, Capabilities::pure()).unwrap_err();
+    assert!(err.to_string().contains("`eval` needs the `eval` capability"), "{err}");
+}
+
+#[test]
+fn evaluated_code_has_the_callers_capabilities() {
+    let program = Box::leak(Box::new(Program::parse(r#"
+        (defCont f [ret]
+            (apply ret (try (eval '(println "hi")) (catch [e] (error-message e)))))
+    "#).unwrap()));
+    let mut interpreter = Interpreter::new();
+    interpreter.load_with_capabilities(program, Capabilities::pure().with(Capability::Eval)).unwrap();
+    let err = interpreter.call("f", ()).unwrap();
+    assert_eq!(err, Data::String("`println` needs the `stdout` capability, which this program is not granted".into()));
+}
@@ -0,0 +1,101 @@
+use continual::{
+    Data,
+    Deadlock,
+    ErrorKind,
+    ScriptError,
+};
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont worker [ret n]
+    (log n)
+    (yield)
+    (log (add n 10))
+    (apply ret (mul n 2)))
+
+(defCont interleave [ret]
+    (spawn worker 1)
+    (spawn worker 2)
+    (log 0)
+    (yield)
+    (log 5)
+    (apply ret #n))
+
+(defCont joined [ret]
+    (set a (spawn worker 3))
+    (set b (spawn worker 4))
+    (apply ret (add (join a) (join b))))
+
+(defCont late [ret]
+    (set t (spawn worker 7))
+    (yield)
+    (yield)
+    (apply ret (add (join t) (join t))))
+
+(defCont waitForMain [ret main]
+    (apply ret (join main)))
+(defCont deadlock [ret]
+    (set t (spawn waitForMain (current-thread)))
+    (apply ret (join t)))
+(defCont joinSelf [ret]
+    (apply ret (join (current-thread))))
+
+(defCont background [ret]
+    (spawn worker 8)
+    (apply ret 1))
+
+(defCont spawnNothing [ret] (spawn))
+(defCont joinNumber [ret] (apply ret (join 5)))
+"#;
+
+
+fn run(name: &str)->(anyhow::Result<Data<'static>>, Vec<i64>) {
+    common::call_logged(SOURCE, name, ())
+}
+
+#[test]
+fn yield_interleaves_threads() {
+    let (res, log) = run("interleave");
+    assert_eq!(res.unwrap(), Data::None);
+    assert_eq!(log, vec![0, 1, 2, 5, 11, 12]);
+}
+
+#[test]
+fn join_returns_results() {
+    let (res, log) = run("joined");
+    assert_eq!(res.unwrap(), Data::Number(6 + 8));
+    assert_eq!(log, vec![3, 4, 13, 14]);
+
+    let (res, _) = run("late");
+    assert_eq!(res.unwrap(), Data::Number(28));
+}
+
+#[test]
+fn the_program_waits_for_every_thread() {
+    let (res, log) = run("background");
+    assert_eq!(res.unwrap(), Data::Number(1));
+    assert_eq!(log, vec![8, 18]);
+}
+
+#[test]
+fn blocked_threads_are_a_deadlock() {
+    let (res, _) = run("deadlock");
+    assert!(res.unwrap_err().is::<Deadlock>());
+
+    let (res, _) = run("joinSelf");
+    assert!(res.unwrap_err().is::<Deadlock>());
+}
+
+#[test]
+fn thread_natives_check_their_args() {
+    let (res, _) = run("spawnNothing");
+    let err = res.unwrap_err();
+    let err = err.downcast_ref::<ScriptError>().unwrap();
+    assert_eq!((err.kind, err.message.as_str()), (ErrorKind::Arity, "`spawn` expects at least 1 args, but got 0"));
+
+    let (res, _) = run("joinNumber");
+    let err = res.unwrap_err();
+    assert_eq!(err.downcast_ref::<ScriptError>().unwrap().kind, ErrorKind::Type);
+}
@@ -0,0 +1,96 @@
+use anyhow::bail;
+use continual::{
+    ContRet,
+    Data,
+};
+use common::nums;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont sorted [ret]
+    (receive [lo hi] (order 7 3))
+    (apply ret (list lo hi)))
+(defCont order [ret a b]
+    (if (eq (rem a b) a) (apply ret a b))
+    (apply ret b a))
+
+(defCont fromValues [ret]
+    (receive [a b c] (values 1 2 3))
+    (apply ret (add a (mul b 10) (mul c 100))))
+
+(defCont fromNative [ret]
+    (receive [q r] (divmod 17 5))
+    (apply ret (list q r)))
+
+(defCont single [ret]
+    (receive [x] 5)
+    (apply ret x))
+
+(defCont firstOnly [ret]
+    (set x (values 1 2))
+    (apply ret x))
+
+(defCont throughScopes [ret]
+    (receive [a b] (match 1 (x (letcc k (apply k x 2)))))
+    (apply ret (list a b)))
+(defCont throughTry [ret]
+    (receive [a b] (try (values 3 4) (catch [e] 0)))
+    (apply ret (list a b)))
+(defCont throughReset [ret]
+    (receive [a b] (reset (values 5 6)))
+    (apply ret (list a b)))
+(defCont throughFinally [ret]
+    (receive [a b] (finally (values 7 8) 0))
+    (apply ret (list a b)))
+
+(defCont mismatch [ret]
+    (apply ret (try
+        (receive [a b] (values 1 2 3))
+        (catch [e] (error-message e)))))
+"#;
+
+
+fn run(name: &str)->Data<'static> {
+    let mut interpreter = common::load(SOURCE);
+    interpreter.add_native("divmod", |_, args|{
+        let [Data::Continuation(ret), Data::Number(a), Data::Number(b)] = args.as_slice() else {
+            bail!("`divmod` expects two numbers");
+        };
+
+        return Ok(ContRet::Apply(ret.clone(), vec![Data::Number(a / b), Data::Number(a % b)]));
+    });
+
+    return interpreter.call(name, ()).unwrap();
+}
+
+#[test]
+fn receive_binds_every_value() {
+    assert_eq!(run("fromValues"), Data::Number(321));
+    assert_eq!(run("sorted"), nums(&[3, 7]));
+}
+
+#[test]
+fn natives_return_several_values() {
+    assert_eq!(run("fromNative"), nums(&[3, 2]));
+}
+
+#[test]
+fn single_values() {
+    assert_eq!(run("single"), Data::Number(5));
+    assert_eq!(run("firstOnly"), Data::Number(1));
+}
+
+#[test]
+fn values_pass_through_scopes_and_prompts() {
+    assert_eq!(run("throughScopes"), nums(&[1, 2]));
+    assert_eq!(run("throughTry"), nums(&[3, 4]));
+    assert_eq!(run("throughReset"), nums(&[5, 6]));
+    assert_eq!(run("throughFinally"), nums(&[7, 8]));
+}
+
+#[test]
+fn the_number_of_values_must_match() {
+    assert_eq!(run("mismatch"), Data::String("`receive` expects 2 values, but got 3".into()));
+}
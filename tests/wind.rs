@@ -0,0 +1,60 @@
+use continual::Data;
+
+mod common;
+
+
+const SOURCE: &str = r#"
+(defCont normal [ret]
+    (apply ret (dynamic-wind (log 1) (add 1 1) (log 3))))
+
+(defCont escape [ret]
+    (dynamic-wind (log 1) (apply ret 5) (log 3)))
+
+(defCont reenter [ret]
+    (set r (dynamic-wind (log 1) (letcc c c) (log 3)))
+    (if (eq r 0) (apply ret 0))
+    (apply r 0))
+
+(defCont thrown [ret]
+    (apply ret (try
+        (dynamic-wind (log 1) (throw "oops") (log 3))
+        (catch [e] 7))))
+
+(defCont four [] 4)
+(defCont tailCall [ret]
+    (apply ret (dynamic-wind (log 1) (apply four) (log 3))))
+
+(defCont cleanup [ret]
+    (apply ret (finally (add 1 2) (log 9))))
+"#;
+
+
+fn run(name: &str)->(Data<'static>, Vec<i64>) {
+    common::run_logged(SOURCE, name, ())
+}
+
+#[test]
+fn after_runs_on_normal_exit() {
+    assert_eq!(run("normal"), (Data::Number(2), vec![1, 3]));
+    assert_eq!(run("cleanup"), (Data::Number(3), vec![9]));
+}
+
+#[test]
+fn after_runs_when_jumping_out() {
+    assert_eq!(run("escape"), (Data::Number(5), vec![1, 3]));
+}
+
+#[test]
+fn before_runs_when_jumping_back_in() {
+    assert_eq!(run("reenter"), (Data::Number(0), vec![1, 3, 1, 3]));
+}
+
+#[test]
+fn after_runs_when_throwing() {
+    assert_eq!(run("thrown"), (Data::Number(7), vec![1, 3]));
+}
+
+#[test]
+fn tail_calls_stay_inside_the_body() {
+    assert_eq!(run("tailCall"), (Data::Number(4), vec![1, 3]));
+}